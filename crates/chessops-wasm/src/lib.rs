@@ -0,0 +1,213 @@
+//! `sochess-core` for the front end, so that it does not need its own copy of the rules.
+//!
+//! Build with `wasm-pack build crates/chessops-wasm --target web`, or
+//! `cargo build -p chessops-wasm --target wasm32-unknown-unknown` followed by `wasm-bindgen`.
+
+use chessops::{Color, Move, Position, PositionError, Role, San, SanAction, SanError, Square};
+use wasm_bindgen::prelude::*;
+
+/**
+ * A position along with whether its King has to move, which the FEN does not record
+ *
+ * Moves can be given in long notation, e.g. `WNf01g03`, or short notation, e.g. `wNg3`.  Legal
+ * moves are listed in long notation, see `toNotation` for the short one.
+ */
+#[wasm_bindgen(js_name = Position)]
+pub struct JsPosition {
+    pos: Position,
+    king_must_move: bool,
+}
+
+#[wasm_bindgen(js_class = Position)]
+impl JsPosition {
+    /// The standard starting position
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::from(Position::new())
+    }
+
+    #[wasm_bindgen(js_name = fromFen)]
+    pub fn from_fen(fen: &str) -> Result<JsPosition, JsError> {
+        Position::try_from_fen(fen)
+            .map(Self::from)
+            .map_err(|err| JsError::new(&format!("Invalid FEN: {}", err)))
+    }
+
+    #[wasm_bindgen(js_name = toFen)]
+    pub fn to_fen(&self) -> String {
+        self.pos.to_fen()
+    }
+
+    /// 1 or 2
+    #[wasm_bindgen(js_name = activePlayer)]
+    pub fn active_player(&self) -> u8 {
+        self.pos.active_player()
+    }
+
+    /// Only King moves are legal after a defection left the King on a square of its own color
+    #[wasm_bindgen(js_name = kingMustMove)]
+    pub fn king_must_move(&self) -> bool {
+        self.king_must_move
+    }
+
+    #[wasm_bindgen(js_name = isCheck)]
+    pub fn is_check(&self) -> bool {
+        self.pos.is_check()
+    }
+
+    #[wasm_bindgen(js_name = isCheckmate)]
+    pub fn is_checkmate(&self) -> bool {
+        self.pos.is_checkmate()
+    }
+
+    /// Play a move and return it in short notation
+    #[wasm_bindgen(js_name = playMove)]
+    pub fn play_move(&mut self, san: &str) -> Result<String, JsError> {
+        let move_ = self.parse_move(san)?;
+        let pos_before = self.pos.clone();
+        let result = if self.king_must_move {
+            self.pos.play_move_after_defect(&move_)
+        } else {
+            self.pos.play_move(&move_)
+        };
+        result.map_err(position_error)?;
+
+        self.king_must_move = false;
+        Ok(San::from_move(&pos_before, &move_))
+    }
+
+    /// Defect to an army the active player controls, e.g. `navy`.  Returns whether the King now
+    /// has to move.
+    #[wasm_bindgen(js_name = defectTo)]
+    pub fn defect_to(&mut self, army: &str) -> Result<bool, JsError> {
+        let color = Color::all()
+            .into_iter()
+            .find(|c| c.to_name() == army)
+            .ok_or_else(|| JsError::new("Unknown army"))?;
+        if self.king_must_move || self.pos.p1_owned().is_none() {
+            return Err(position_error(PositionError::IllegalMove));
+        }
+
+        match self.pos.defect_to(color) {
+            Ok(()) => {}
+            Err(PositionError::DefectMoveKing) => self.king_must_move = true,
+            Err(err) => return Err(position_error(err)),
+        }
+        Ok(self.king_must_move)
+    }
+
+    /// Player 2's choice after the first move: keep it, or have Player 1 play Black instead
+    #[wasm_bindgen(js_name = chooseFirstMove)]
+    pub fn choose_first_move(&mut self, accept: bool) -> Result<(), JsError> {
+        if self.pos.p1_owned().is_some() || self.pos.active_player() != 2 {
+            return Err(JsError::new("There is no first move to choose"));
+        }
+        if accept {
+            self.pos.accept_first_move();
+        } else {
+            self.pos.reject_first_move();
+        }
+        Ok(())
+    }
+
+    /// All legal moves in long notation
+    #[wasm_bindgen(js_name = legalMoves)]
+    pub fn legal_moves(&self) -> Vec<String> {
+        self.moves().iter().map(|m| m.to_san()).collect()
+    }
+
+    /// Legal moves of the piece on `square`, e.g. `g1`, in long notation
+    #[wasm_bindgen(js_name = legalMovesFrom)]
+    pub fn legal_moves_from(&self, square: &str) -> Result<Vec<String>, JsError> {
+        let from = Square::from_short_str(square).ok_or_else(|| JsError::new("Invalid square"))?;
+        Ok(self
+            .moves()
+            .iter()
+            .filter(|m| m.from == from)
+            .map(|m| m.to_san())
+            .collect())
+    }
+
+    /// Armies the active player can defect to
+    #[wasm_bindgen(js_name = defections)]
+    pub fn defections(&self) -> Vec<String> {
+        if self.king_must_move {
+            return Vec::new();
+        }
+        let mut names: Vec<String> = self
+            .pos
+            .controlled(self.pos.active_player())
+            .iter()
+            .map(|c| c.to_name().to_string())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Short notation of a legal move given in long notation, e.g. `WNf01g03` to `wNg3`
+    #[wasm_bindgen(js_name = toNotation)]
+    pub fn to_notation(&self, san: &str) -> Result<String, JsError> {
+        let move_ = Move::try_from_san(san).ok_or_else(|| san_error(SanError::InvalidNotation))?;
+        // Legal moves leave out promotions
+        if !self
+            .moves()
+            .iter()
+            .any(|m| m.from == move_.from && m.to == move_.to)
+        {
+            return Err(san_error(SanError::IllegalMove));
+        }
+        Ok(San::from_move(&self.pos, &move_))
+    }
+
+    /// Long notation of a move given in short notation, e.g. `wNg3` to `WNf01g03`
+    #[wasm_bindgen(js_name = fromNotation)]
+    pub fn from_notation(&self, notation: &str) -> Result<String, JsError> {
+        match San::parse(&self.pos, notation).map_err(san_error)? {
+            SanAction::Move(move_) => Ok(move_.to_san()),
+            SanAction::Defect(_) => Err(JsError::new("Not a move, use defectTo")),
+        }
+    }
+}
+
+impl Default for JsPosition {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Position> for JsPosition {
+    fn from(pos: Position) -> Self {
+        Self {
+            pos,
+            king_must_move: false,
+        }
+    }
+}
+
+impl JsPosition {
+    fn moves(&self) -> Vec<Move> {
+        self.pos
+            .legal_moves()
+            .into_iter()
+            .filter(|m| !self.king_must_move || m.role == Role::King)
+            .collect()
+    }
+
+    fn parse_move(&self, san: &str) -> Result<Move, JsError> {
+        if san.starts_with(|c: char| c.is_ascii_uppercase()) {
+            return Move::try_from_san(san).ok_or_else(|| san_error(SanError::InvalidNotation));
+        }
+        match San::parse(&self.pos, san).map_err(san_error)? {
+            SanAction::Move(move_) => Ok(move_),
+            SanAction::Defect(_) => Err(JsError::new("Not a move, use defectTo")),
+        }
+    }
+}
+
+fn position_error(err: PositionError) -> JsError {
+    JsError::from(err)
+}
+
+fn san_error(err: SanError) -> JsError {
+    JsError::from(err)
+}
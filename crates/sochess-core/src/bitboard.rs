@@ -2,7 +2,7 @@ use std::fmt;
 
 use bit_vec::BitVec;
 
-use crate::chessops::{File, Quadrant, Rank, BOARD_SIZE, BOARD_WIDTH};
+use crate::{File, Quadrant, Rank, BOARD_SIZE, BOARD_WIDTH};
 
 /**
  * Bitboard for 256 square Sovereign Chess board.
@@ -34,8 +34,13 @@ impl fmt::Display for Bitboard {
     }
 }
 
+impl Default for Bitboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Bitboard {
-    #[cfg(test)]
     pub fn from_bytes(bytes: &[u8]) -> Self {
         Self {
             bv: BitVec::from_bytes(bytes),
@@ -138,7 +143,7 @@ impl Bitboard {
     }
 
     #[cfg(test)]
-    pub fn len(&self) -> usize {
+    pub(crate) fn len(&self) -> usize {
         self.bv.len()
     }
 
@@ -208,13 +213,22 @@ impl Bitboard {
     pub fn any(&self) -> bool {
         self.bv.any()
     }
+
+    /// Indices of all set bits, from lowest to highest
+    pub fn iter_ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.bv
+            .iter()
+            .enumerate()
+            .filter(|(_, bit)| *bit)
+            .map(|(i, _)| i)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use crate::chessops::Square;
+    use crate::Square;
 
     #[test]
     fn shift_right_works() {
@@ -1,8 +1,9 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 
-use crate::chessops::{movegen, Bitboard, Color, LookupTables, Move, Piece, Role, Square};
+use crate::{movegen, Bitboard, Color, File, LookupTables, Move, Piece, Role, Square, BOARD_WIDTH};
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Board {
     pub by_square: HashMap<Square, Piece>,
     /// e.g. locations of all white pawns
@@ -13,6 +14,51 @@ pub struct Board {
     occupied_colored_squares: HashSet<Color>,
 }
 
+/**
+ * Grid from rank 16 down to rank 1.  Each square is three characters wide:
+ * - ` wN`: a piece, as army color and role
+ * - `*wN`: a piece on a colored square
+ * - `  n`: an empty colored square, as its army color
+ * - `  .`: an empty square
+ */
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let files: String = File::iter()
+            .map(|file| format!("  {}", file.to_str()))
+            .collect();
+
+        writeln!(f, "   {}", files)?;
+        for rank in (0..BOARD_WIDTH).rev() {
+            write!(f, "{:>2} ", rank + 1)?;
+            for file in 0..BOARD_WIDTH {
+                let square = Square::from_file_and_rank_index(file, rank);
+                match (self.by_square.get(&square), square.color()) {
+                    (Some(piece), color) => {
+                        let marker = if color.is_some() { '*' } else { ' ' };
+                        write!(
+                            f,
+                            "{}{}{}",
+                            marker,
+                            piece.color.to_char(),
+                            piece.role.to_char().to_ascii_uppercase(),
+                        )?;
+                    }
+                    (None, Some(color)) => write!(f, "  {}", color.to_char())?,
+                    (None, None) => write!(f, "  .")?,
+                }
+            }
+            writeln!(f, " {:>2}", rank + 1)?;
+        }
+        write!(f, "   {}", files)
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Board {
     pub fn new() -> Self {
         Self {
@@ -87,7 +133,25 @@ impl Board {
         }
     }
 
-    #[cfg(test)]
+    /// Remove whatever piece is on `square`, keeping the bitboards in sync.
+    pub fn remove_piece_at(&mut self, square: &Square) -> Option<Piece> {
+        let piece = self.by_square.remove(square)?;
+
+        if let Some(loc) = self.by_piece.get_mut(&piece) {
+            loc.set(square.to_index(), false);
+        }
+        if let Some(color_loc) = self.by_color.get_mut(&piece.color) {
+            color_loc.set(square.to_index(), false);
+        }
+        self.all_pieces.set(square.to_index(), false);
+
+        if let Some(color) = square.color() {
+            self.occupied_colored_squares.remove(&color);
+        }
+
+        Some(piece)
+    }
+
     pub fn get(&self, square: &Square) -> Option<&Piece> {
         self.by_square.get(square)
     }
@@ -104,6 +168,14 @@ impl Board {
         Some(Square::from_index(index))
     }
 
+    /// Like `find`, but returns `None` instead of panicking when the piece is missing
+    pub fn find_king(&self, color: &Color) -> Option<Square> {
+        self.by_piece
+            .get(&Piece::new(*color, Role::King))
+            .and_then(|bitboard| bitboard.least_significant_bit())
+            .map(Square::from_index)
+    }
+
     pub fn is_legal_move(
         &self,
         move_: &Move,
@@ -117,8 +189,21 @@ impl Board {
             .by_piece
             .get(&piece)
             .expect("Corresponing piece is not on the board");
+
+        let legal_moves = self.compute_legal_moves(&move_.from, &piece, own_side, enemy_side);
+        legal_moves.get(move_.to.to_index()).unwrap()
+    }
+
+    /// Return a bitboard of every square `piece` can move to from `from`
+    pub fn compute_legal_moves(
+        &self,
+        from: &Square,
+        piece: &Piece,
+        own_side: &HashSet<Color>,
+        enemy_side: &HashSet<Color>,
+    ) -> Bitboard {
         let mut start_loc = Bitboard::new();
-        start_loc.set(move_.from.clone() as usize, true);
+        start_loc.set(from.to_index(), true);
 
         // Construct bitboard for own side's pieces
         let mut own_side_bitboard = Bitboard::new();
@@ -136,7 +221,7 @@ impl Board {
             }
         }
 
-        let mut legal_moves = match move_.role {
+        let mut legal_moves = match piece.role {
             Role::Bishop => {
                 movegen::compute_bishop_moves(&start_loc, &own_side_bitboard, &enemy_side_bitboard)
             }
@@ -181,11 +266,11 @@ impl Board {
             &self
                 .lookup_tables
                 .clear_colored_squares
-                .get(&move_.color)
+                .get(&piece.color)
                 .unwrap(),
         );
 
-        legal_moves.get(move_.to.to_index()).unwrap()
+        legal_moves
     }
 
     /// Return a bitboard with valid moves to legal colored squares
@@ -1,4 +1,9 @@
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum Color {
     Ash, // Light grey
     Black,
@@ -34,8 +39,9 @@ impl Color {
     }
 
     /// Useful when you have the color code in a string slice.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
-        let ch = s.to_lowercase().chars().nth(0).expect("Invalid color code");
+        let ch = s.to_lowercase().chars().next()?;
         Color::from_char(ch)
     }
 
@@ -56,6 +62,23 @@ impl Color {
         }
     }
 
+    pub fn to_name(self) -> &'static str {
+        match self {
+            Self::Ash => "ash",
+            Self::Black => "black",
+            Self::Cyan => "cyan",
+            Self::Green => "green",
+            Self::Navy => "navy",
+            Self::Orange => "orange",
+            Self::Pink => "pink",
+            Self::Red => "red",
+            Self::Slate => "slate",
+            Self::Violet => "violet",
+            Self::White => "white",
+            Self::Yellow => "yellow",
+        }
+    }
+
     pub fn all() -> [Color; 12] {
         [
             Color::Ash,
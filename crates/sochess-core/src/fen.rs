@@ -0,0 +1,371 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use crate::{Board, Color, Piece, Player, PositionProblem, Role, Square, BOARD_SIZE, BOARD_WIDTH};
+
+/**
+ * Forsyth-Edwards Notation - notates the resulting board position
+ * Fields:
+ * - Piece placement.  Each piece is represented by two characters.  Color and Role
+ * - Active player: 1 or 0
+ * - Player 1's owned army.  e.g. `W` for White army
+ * - Player 1's controlled armies.  e.g. `GY` for Green and Yellow armies
+ * - Player 2's owned army
+ * - Player 2's controlled armies
+ * - Ply or halfmove number.  Starts at 1 after first move.
+ */
+pub struct Fen {}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FenError {
+    /// Not the seven space-separated fields
+    InvalidFormat,
+    /// Unknown piece, or a rank that does not add up to 16 squares
+    InvalidBoard,
+    InvalidActivePlayer,
+    InvalidArmies,
+    InvalidPly,
+    /// Well-formed, but not a position that can be played, see `Position::validate`
+    InvalidPosition(Vec<PositionProblem>),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::InvalidFormat => write!(f, "expected seven space-separated fields"),
+            FenError::InvalidBoard => write!(f, "invalid piece placement"),
+            FenError::InvalidActivePlayer => write!(f, "invalid active player"),
+            FenError::InvalidArmies => write!(f, "invalid owned or controlled armies"),
+            FenError::InvalidPly => write!(f, "invalid ply"),
+            FenError::InvalidPosition(problems) => {
+                let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+                write!(f, "invalid position: {}", problems.join(", "))
+            }
+        }
+    }
+}
+
+impl Error for FenError {}
+
+/// The fields of a FEN, in order
+pub type FenFields = (
+    Board,
+    Player,
+    Option<Color>,
+    HashSet<Color>,
+    Option<Color>,
+    HashSet<Color>,
+    u32,
+);
+
+impl Fen {
+    /// Like `try_parse`, but panics on invalid FENs.  Meant for FENs we wrote ourselves.
+    pub fn parse(fen: &str) -> FenFields {
+        Fen::try_parse(fen).expect("Invalid FEN")
+    }
+
+    pub fn try_parse(fen: &str) -> Result<FenFields, FenError> {
+        let parts: Vec<&str> = fen.split(' ').collect();
+        if parts.len() != 7 {
+            return Err(FenError::InvalidFormat);
+        }
+
+        let active_player = match parts[1].len() {
+            1 => Player::from_char(parts[1].chars().nth(0).unwrap()),
+            _ => None,
+        }
+        .ok_or(FenError::InvalidActivePlayer)?;
+
+        Ok((
+            // Board
+            Fen::try_to_board(parts[0])?,
+            // Active player
+            active_player,
+            // P1 owned color
+            Fen::parse_owned(parts[2])?,
+            // P1 controlled colors
+            Fen::parse_controlled(parts[3])?,
+            // P2 owned color
+            Fen::parse_owned(parts[4])?,
+            // P2 controlled colors
+            Fen::parse_controlled(parts[5])?,
+            // Ply
+            parts[6].parse::<u32>().map_err(|_| FenError::InvalidPly)?,
+        ))
+    }
+
+    /// `-` or a single color
+    fn parse_owned(field: &str) -> Result<Option<Color>, FenError> {
+        match field {
+            "-" => Ok(None),
+            _ if field.len() == 1 => Color::from_char(field.chars().nth(0).unwrap())
+                .map(Some)
+                .ok_or(FenError::InvalidArmies),
+            _ => Err(FenError::InvalidArmies),
+        }
+    }
+
+    /// `-` or any number of colors
+    fn parse_controlled(field: &str) -> Result<HashSet<Color>, FenError> {
+        if field == "-" {
+            return Ok(HashSet::new());
+        }
+        field
+            .chars()
+            .map(|ch| Color::from_char(ch).ok_or(FenError::InvalidArmies))
+            .collect()
+    }
+
+    /// FEN order goes from top rank to bottom rank, while square order goes from
+    /// bottom rank to top.  But in between ranks, we iterate through files the same order.
+    fn fen_index_to_square(fen_index: usize) -> Square {
+        let rank = if fen_index == 0 {
+            BOARD_WIDTH - 1
+        } else {
+            (BOARD_WIDTH - (fen_index / BOARD_WIDTH)) - 1
+        };
+        let file = fen_index % BOARD_WIDTH;
+        Square::from_file_and_rank_index(file, rank)
+    }
+
+    /// Like `try_to_board`, but panics on invalid placements
+    #[cfg(test)]
+    pub fn to_board(board_fen: &str) -> Board {
+        Fen::try_to_board(board_fen).expect("Invalid piece placement")
+    }
+
+    pub fn try_to_board(board_fen: &str) -> Result<Board, FenError> {
+        let mut board = Board::new();
+
+        // Split fen into ranks
+        let ranks: Vec<&str> = board_fen.split("/").collect();
+        if ranks.len() != BOARD_WIDTH {
+            return Err(FenError::InvalidBoard);
+        }
+
+        let mut index = 0;
+
+        for (rank_index, rank) in ranks.into_iter().enumerate() {
+            let mut iter = rank.chars();
+
+            // Read two chars each loop iteration
+            while let Some(color) = iter.nth(0) {
+                let role = iter.nth(0).ok_or(FenError::InvalidBoard)?;
+
+                if color.is_digit(10) {
+                    // base 10
+                    // Skip this many spaces
+                    let num_skipped: usize = format!("{}{}", color, role)
+                        .parse()
+                        .map_err(|_| FenError::InvalidBoard)?;
+                    index += num_skipped;
+                } else {
+                    let piece = Piece {
+                        color: Color::from_char(color).ok_or(FenError::InvalidBoard)?,
+                        role: Role::from_char(role).ok_or(FenError::InvalidBoard)?,
+                    };
+                    if index >= (rank_index + 1) * BOARD_WIDTH {
+                        return Err(FenError::InvalidBoard);
+                    }
+                    let square = Fen::fen_index_to_square(index);
+                    board.insert_piece(square, piece);
+                    index += 1;
+                }
+            }
+
+            if index != (rank_index + 1) * BOARD_WIDTH {
+                return Err(FenError::InvalidBoard);
+            }
+        }
+
+        Ok(board)
+    }
+
+    pub fn from_board(board: &Board) -> String {
+        let mut tokens = Vec::with_capacity(BOARD_SIZE);
+        let mut empty_squares = 0;
+
+        for i in 0..BOARD_SIZE {
+            // If new rank, add a separator
+            if i != 0 && i % BOARD_WIDTH == 0 {
+                if empty_squares > 0 {
+                    tokens.push(format!("{:02}", empty_squares));
+                    empty_squares = 0;
+                }
+                tokens.push("/".to_string());
+            }
+
+            let square = Fen::fen_index_to_square(i);
+            match board.by_square.get(&square) {
+                Some(piece) => {
+                    // Add skipped squares count
+                    if empty_squares > 0 {
+                        tokens.push(format!("{:02}", empty_squares));
+                        empty_squares = 0;
+                    }
+                    tokens.push(piece.to_string());
+                }
+                None => {
+                    empty_squares += 1;
+                }
+            }
+        }
+
+        if empty_squares > 0 {
+            tokens.push(format!("{:02}", empty_squares));
+        }
+
+        tokens.join("")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    #[test]
+    fn to_board() {
+        let board_fen = "aqabvrvnbrbnbbbqbkbbbnbrynyrsbsq/aranvpvpbpbpbpbpbpbpbpbpypypsnsr/nbnp12opob/nqnp12opoq/crcp12rprr/cncp12rprn/gbgp12pppb/gqgp12pppq/yqyp12vpvq/ybyp12vpvb/onop12npnn/orop12npnr/rqrp12cpcq/rbrp12cpcb/srsnppppwpwpwpwpwpwpwpwpgpgpanar/sqsbprpnwrwnwbwqwkwbwnwrgngrabaq";
+        let board = Fen::to_board(board_fen);
+        assert_eq!(
+            *board.by_square.get(&Square::A16).unwrap(),
+            Piece {
+                color: Color::Ash,
+                role: Role::Queen,
+            },
+        );
+        assert_eq!(
+            *board.by_square.get(&Square::C2).unwrap(),
+            Piece {
+                color: Color::Pink,
+                role: Role::Pawn,
+            },
+        );
+        assert_eq!(
+            *board.by_square.get(&Square::P16).unwrap(),
+            Piece {
+                color: Color::Slate,
+                role: Role::Queen,
+            },
+        );
+        assert!(board.by_square.get(&Square::I8).is_none());
+    }
+
+    #[test]
+    fn try_parse_rejects_invalid_fens() {
+        let board = "wk15/16/16/16/16/16/16/16/16/16/16/16/16/16/16/bk15";
+        assert!(Fen::try_parse(&format!("{} 1 w - b - 0", board)).is_ok());
+        assert!(Fen::try_parse(&format!("{} 1 - - - - 0", board)).is_ok());
+
+        assert_eq!(
+            Fen::try_parse(&format!("{} 1 w - b -", board)).unwrap_err(),
+            FenError::InvalidFormat,
+        );
+        assert_eq!(
+            Fen::try_parse(&format!("{} 3 w - b - 0", board)).unwrap_err(),
+            FenError::InvalidActivePlayer,
+        );
+        assert_eq!(
+            Fen::try_parse(&format!("{} 1 x - b - 0", board)).unwrap_err(),
+            FenError::InvalidArmies,
+        );
+        assert_eq!(
+            Fen::try_parse(&format!("{} 1 w gx b - 0", board)).unwrap_err(),
+            FenError::InvalidArmies,
+        );
+        assert_eq!(
+            Fen::try_parse(&format!("{} 1 w - b - -1", board)).unwrap_err(),
+            FenError::InvalidPly,
+        );
+    }
+
+    #[test]
+    fn try_to_board_rejects_invalid_placements() {
+        // Too few ranks
+        assert!(Fen::try_to_board("16/16").is_err());
+        // Rank with 17 squares
+        assert!(Fen::try_to_board("wk16/16/16/16/16/16/16/16/16/16/16/16/16/16/16/16").is_err());
+        // Rank with 15 squares
+        assert!(Fen::try_to_board("wk14/16/16/16/16/16/16/16/16/16/16/16/16/16/16/16").is_err());
+        // Unknown role
+        assert!(Fen::try_to_board("wz15/16/16/16/16/16/16/16/16/16/16/16/16/16/16/16").is_err());
+        // Odd number of characters
+        assert!(Fen::try_to_board("w15/16/16/16/16/16/16/16/16/16/16/16/16/16/16/16").is_err());
+    }
+
+    #[test]
+    fn fen_index_to_square_works() {
+        assert_eq!(Fen::fen_index_to_square(0), Square::A16);
+        assert_eq!(Fen::fen_index_to_square(5), Square::F16);
+        assert_eq!(Fen::fen_index_to_square(17), Square::B15);
+        assert_eq!(Fen::fen_index_to_square(250), Square::K1);
+        assert_eq!(Fen::fen_index_to_square(255), Square::P1);
+    }
+
+    #[test]
+    fn from_board_works() {
+        let pieces = HashMap::from([(
+            Square::A1,
+            Piece {
+                color: Color::White,
+                role: Role::King,
+            },
+        )]);
+        let mut board = Board::new();
+        board.by_square = pieces;
+        assert_eq!(
+            Fen::from_board(&board),
+            "16/16/16/16/16/16/16/16/16/16/16/16/16/16/16/wk15".to_string(),
+        );
+
+        board.by_square = HashMap::from([(
+            Square::A16,
+            Piece {
+                color: Color::White,
+                role: Role::King,
+            },
+        )]);
+        assert_eq!(
+            Fen::from_board(&board),
+            "wk15/16/16/16/16/16/16/16/16/16/16/16/16/16/16/16".to_string(),
+        );
+
+        board.by_square = HashMap::from([
+            (
+                Square::A16,
+                Piece {
+                    color: Color::White,
+                    role: Role::King,
+                },
+            ),
+            (
+                Square::G13,
+                Piece {
+                    color: Color::Black,
+                    role: Role::Pawn,
+                },
+            ),
+        ]);
+        assert_eq!(
+            Fen::from_board(&board),
+            "wk15/16/16/06bp09/16/16/16/16/16/16/16/16/16/16/16/16".to_string(),
+        );
+    }
+
+    #[test]
+    fn fen_error_display_works() {
+        assert_eq!(FenError::InvalidPly.to_string(), "invalid ply");
+        assert_eq!(
+            FenError::InvalidPosition(vec![
+                PositionProblem::OneOwner,
+                PositionProblem::MissingKing(Color::Navy),
+            ])
+            .to_string(),
+            "invalid position: only one player owns an army, navy has no King",
+        );
+    }
+}
@@ -30,6 +30,10 @@ impl File {
         FILE_IDS.iter().position(|&x| x == s).expect("Invalid file")
     }
 
+    pub fn to_str(self) -> &'static str {
+        FILE_IDS[self.to_index()]
+    }
+
     pub fn from_index(i: usize) -> Self {
         ALL_FILES[i].clone()
     }
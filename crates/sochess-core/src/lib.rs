@@ -0,0 +1,69 @@
+//! Sovereign Chess rules engine: positions, move generation, FEN and notation.
+//!
+//! Start from `Position::new()` or `Position::try_from_fen`, then list moves with
+//! `Position::legal_moves` and play them with `Position::play_move` or `Position::defect_to`.
+//! `San` reads and writes the short notation meant for players, e.g. `wNg3`.
+//!
+//! With the `serde` feature, errors and the types above implement `Serialize` and `Deserialize`,
+//! see `serde_impls` for their representations.
+
+mod bitboard;
+mod board;
+mod color;
+mod fen;
+mod file;
+mod lookup_tables;
+mod move_type;
+mod movegen;
+mod piece;
+mod player;
+mod position;
+mod quadrant;
+mod rank;
+mod role;
+mod san;
+#[cfg(feature = "serde")]
+mod serde_impls;
+mod shuffle;
+mod square;
+
+pub use bitboard::Bitboard;
+pub use board::Board;
+pub use color::Color;
+pub(crate) use fen::Fen;
+pub use fen::FenError;
+pub use file::File;
+pub use lookup_tables::LookupTables;
+pub use move_type::Move;
+pub use piece::Piece;
+pub(crate) use player::Player;
+pub use position::{Position, PositionError, PositionProblem};
+pub use quadrant::Quadrant;
+pub use rank::Rank;
+pub use role::Role;
+pub use san::{San, SanAction, SanError};
+pub use shuffle::Shuffle;
+pub use square::Square;
+
+pub const BOARD_WIDTH: usize = 16;
+pub const BOARD_SIZE: usize = BOARD_WIDTH * BOARD_WIDTH;
+
+/// Like `assert_eq!`, but prints both positions as boards when they differ.
+#[cfg(test)]
+macro_rules! assert_position_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if left != right {
+                    panic!(
+                        "assertion `left == right` failed\n left:\n{}\n\nright:\n{}",
+                        left, right,
+                    );
+                }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+pub(crate) use assert_position_eq;
@@ -1,8 +1,8 @@
 use std::collections::HashMap;
 
-use crate::chessops::{Bitboard, Color, File, Quadrant, Rank, Square};
+use crate::{Bitboard, Color, File, Quadrant, Rank, Square};
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct LookupTables {
     pub clear_file: Vec<Bitboard>,
     pub clear_rank: Vec<Bitboard>,
@@ -12,6 +12,12 @@ pub struct LookupTables {
     pub clear_colored_squares: HashMap<Color, Bitboard>,
 }
 
+impl Default for LookupTables {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LookupTables {
     pub fn new() -> Self {
         let mut clear_file = Vec::new();
@@ -56,7 +62,8 @@ impl LookupTables {
         }
     }
 
-    fn build_clear_colored_squares() -> HashMap<Color, Bitboard> {
+    /// For each color, a full bitboard with its two colored squares cleared
+    pub fn build_clear_colored_squares() -> HashMap<Color, Bitboard> {
         let mut map = HashMap::new();
 
         for color in Color::all() {
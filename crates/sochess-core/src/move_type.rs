@@ -0,0 +1,163 @@
+use crate::{Color, Piece, Role, Square};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Move {
+    pub color: Color,
+    pub role: Role,
+    pub from: Square,
+    pub to: Square,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub promotion: Option<Role>,
+}
+
+impl Move {
+    pub fn new(color: Color, role: Role, from: Square, to: Square) -> Self {
+        Move {
+            color: color,
+            role: role,
+            from: from,
+            to: to,
+            promotion: None,
+        }
+    }
+
+    pub fn from_san(san: &str) -> Self {
+        assert!(san.len() >= 8);
+        assert!(san.len() <= 10);
+        let lcase_san = san.to_lowercase();
+
+        let mut move_ = Self {
+            color: Color::from_char(lcase_san[0..1].chars().nth(0).unwrap())
+                .expect("Invalid move color"),
+            role: Role::from_char(lcase_san[1..2].chars().nth(0).unwrap())
+                .expect("Invalid move role"),
+            from: Square::from_str(&lcase_san[2..5]),
+            to: Square::from_str(&lcase_san[5..8]),
+            promotion: None,
+        };
+
+        if san.len() > 8 {
+            let promotion = if lcase_san[8..9].chars().nth(0).unwrap() == '=' {
+                Some(
+                    Role::from_char(lcase_san[9..10].chars().nth(0).unwrap())
+                        .expect("Invalid role"),
+                )
+            } else {
+                None
+            };
+            move_.promotion = promotion;
+        }
+
+        move_
+    }
+
+    /// Like `from_san`, but returns `None` for malformed notation instead of panicking
+    pub fn try_from_san(san: &str) -> Option<Self> {
+        if !san.is_ascii() || !(8..=10).contains(&san.len()) {
+            return None;
+        }
+        let lcase_san = san.to_lowercase();
+        let chars: Vec<char> = lcase_san.chars().collect();
+        // Ranks are zero-padded to two digits
+        let square = |s: &str| {
+            let (file, rank) = s.split_at(1);
+            if !rank.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            Square::from_short_str(&format!("{}{}", file, rank.trim_start_matches('0')))
+        };
+
+        let promotion = match &lcase_san[8..] {
+            "" => None,
+            p if p.len() == 2 && p.starts_with('=') => Some(Role::from_char(chars[9])?),
+            _ => return None,
+        };
+
+        Some(Self {
+            color: Color::from_char(chars[0])?,
+            role: Role::from_char(chars[1])?,
+            from: square(&lcase_san[2..5])?,
+            to: square(&lcase_san[5..8])?,
+            promotion,
+        })
+    }
+
+    /// Inverse of `from_san`, e.g. `WNb01c03`
+    pub fn to_san(&self) -> String {
+        let mut san = format!(
+            "{}{}{}{}",
+            self.color.to_char().to_ascii_uppercase(),
+            self.role.to_char().to_ascii_uppercase(),
+            self.from.to_str(),
+            self.to.to_str(),
+        );
+        if let Some(role) = &self.promotion {
+            san.push('=');
+            san.push(role.to_char().to_ascii_uppercase());
+        }
+        san
+    }
+
+    pub fn to_piece(&self) -> Piece {
+        Piece {
+            color: self.color.clone(),
+            role: self.role.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_san_works() {
+        assert_eq!(
+            Move::from_san("WNb01c03"),
+            Move::new(Color::White, Role::Knight, Square::B1, Square::C3),
+        );
+    }
+
+    #[test]
+    fn try_from_san_works() {
+        assert_eq!(
+            Move::try_from_san("WNb01c03"),
+            Some(Move::from_san("WNb01c03"))
+        );
+        assert_eq!(
+            Move::try_from_san("WPi06i07=Q"),
+            Some(Move::from_san("WPi06i07=Q"))
+        );
+        assert_eq!(Move::try_from_san("WNb01"), None);
+        assert_eq!(Move::try_from_san("WNb01c17"), None);
+        assert_eq!(Move::try_from_san("WZb01c03"), None);
+        assert_eq!(Move::try_from_san("WPi06i07Q"), None);
+    }
+
+    #[test]
+    fn to_san_works() {
+        assert_eq!(
+            Move::new(Color::White, Role::Knight, Square::B1, Square::C3).to_san(),
+            "WNb01c03",
+        );
+        assert_eq!(Move::from_san("WPi06i07=Q").to_san(), "WPi06i07=Q");
+    }
+
+    #[test]
+    fn from_san_with_promotion_works() {
+        assert_eq!(
+            Move::from_san("WPi06i07=Q"),
+            Move {
+                color: Color::White,
+                role: Role::Pawn,
+                from: Square::I6,
+                to: Square::I7,
+                promotion: Some(Role::Queen),
+            },
+        );
+    }
+}
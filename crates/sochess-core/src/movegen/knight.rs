@@ -1,4 +1,4 @@
-use crate::chessops::{Bitboard, File, LookupTables, BOARD_WIDTH};
+use crate::{Bitboard, File, LookupTables, BOARD_WIDTH};
 
 /**
 * Explanation: https://pages.cs.wisc.edu/~psilord/blog/data/chess-pages/nonsliding.html
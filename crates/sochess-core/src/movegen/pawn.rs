@@ -1,4 +1,4 @@
-use crate::chessops::{Bitboard, File, LookupTables, Quadrant, Rank, BOARD_WIDTH};
+use crate::{Bitboard, File, LookupTables, Quadrant, Rank, BOARD_WIDTH};
 
 pub fn compute_pawn_moves(
     start_location: &Bitboard,
@@ -1,4 +1,4 @@
-use crate::chessops::{movegen::MAX_RANGE, Bitboard, File, Square, BOARD_SIZE, BOARD_WIDTH};
+use crate::{movegen::MAX_RANGE, Bitboard, File, Square, BOARD_SIZE, BOARD_WIDTH};
 
 /**
  * Ray directions for Rook
@@ -1,4 +1,4 @@
-use crate::chessops::{Color, Role};
+use crate::{Color, Role};
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Piece {
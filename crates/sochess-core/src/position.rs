@@ -0,0 +1,873 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use crate::{Board, Color, Fen, FenError, Move, Piece, Player, Role, Square};
+
+const INITIAL_FEN: &'static str = "aqabvrvnbrbnbbbqbkbbbnbrynyrsbsq/aranvpvpbpbpbpbpbpbpbpbpypypsnsr/nbnp12opob/nqnp12opoq/crcp12rprr/cncp12rprn/gbgp12pppb/gqgp12pppq/yqyp12vpvq/ybyp12vpvb/onop12npnn/orop12npnr/rqrp12cpcq/rbrp12cpcb/srsnppppwpwpwpwpwpwpwpwpgpgpanar/sqsbprpnwrwnwbwqwkwbwnwrgngrabaq 1 - - - - 0";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PositionError {
+    IllegalMove,
+    /// The piece belongs to an army the active player neither owns nor controls
+    NotOwnColor,
+    FirstMoveNotWhite,
+    /// The defection left the King on a square of its own color, so it has to move next
+    DefectMoveKing,
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            PositionError::IllegalMove => "illegal move",
+            PositionError::NotOwnColor => "the active player does not control that army",
+            PositionError::FirstMoveNotWhite => "the first move must be made by a white piece",
+            PositionError::DefectMoveKing => {
+                "the King is on a square of its own color and must move"
+            }
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl Error for PositionError {}
+
+/// An inconsistency found by `Position::validate`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PositionProblem {
+    /// Only one of the players owns an army
+    OneOwner,
+    /// Both players own this army
+    OwnedByBoth(Color),
+    /// This army is owned by one player and controlled by one of them
+    OwnedAndControlled(Color),
+    /// Both players control this army
+    ControlledByBoth(Color),
+    /// This army is owned, or is White or Black before the first move, but has no King
+    MissingKing(Color),
+    /// This army has more than one King
+    ExtraKing(Color),
+    /// This army has a King but nobody owns it
+    UnownedKing(Color),
+}
+
+impl fmt::Display for PositionProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PositionProblem::OneOwner => write!(f, "only one player owns an army"),
+            PositionProblem::OwnedByBoth(c) => write!(f, "both players own {}", c.to_name()),
+            PositionProblem::OwnedAndControlled(c) => {
+                write!(f, "{} is both owned and controlled", c.to_name())
+            }
+            PositionProblem::ControlledByBoth(c) => {
+                write!(f, "both players control {}", c.to_name())
+            }
+            PositionProblem::MissingKing(c) => write!(f, "{} has no King", c.to_name()),
+            PositionProblem::ExtraKing(c) => write!(f, "{} has more than one King", c.to_name()),
+            PositionProblem::UnownedKing(c) => {
+                write!(f, "{} has a King but nobody owns it", c.to_name())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+    board: Board,
+    active_player: Player,
+    p1_owned: Option<Color>,
+    p1_controlled: HashSet<Color>,
+    p2_owned: Option<Color>,
+    p2_controlled: HashSet<Color>,
+    ply: u32,
+}
+
+/// The board grid, followed by a summary of whose turn it is and which armies each player holds.
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn armies(owned: &Option<Color>, controlled: &HashSet<Color>) -> String {
+            let mut controlled: Vec<&str> = controlled.iter().map(|c| c.to_name()).collect();
+            controlled.sort_unstable();
+            let controlled = if controlled.is_empty() {
+                "none".to_string()
+            } else {
+                controlled.join(", ")
+            };
+
+            format!(
+                "owns {}, controls {}",
+                owned.map(|c| c.to_name()).unwrap_or("none"),
+                controlled,
+            )
+        }
+
+        writeln!(f, "{}", self.board)?;
+        write!(
+            f,
+            "Player {} to move | Player 1 {} | Player 2 {} | Ply {}",
+            self.active_player.to_int(),
+            armies(&self.p1_owned, &self.p1_controlled),
+            armies(&self.p2_owned, &self.p2_controlled),
+            self.ply,
+        )
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Position {
+    pub fn new_fen() -> String {
+        String::from(INITIAL_FEN)
+    }
+
+    /// The standard starting position
+    pub fn new() -> Self {
+        Self::from_fen(Self::new_fen())
+    }
+
+    /// Like `from_fen`, but for FENs we did not write ourselves, e.g. custom starting positions.
+    /// Checks the syntax, then the position itself, see `validate`.
+    pub fn try_from_fen(fen: &str) -> Result<Self, FenError> {
+        let pos = Self::try_from_fen_unvalidated(fen)?;
+
+        let problems = pos.validate();
+        if !problems.is_empty() {
+            return Err(FenError::InvalidPosition(problems));
+        }
+        Ok(pos)
+    }
+
+    /// Like `try_from_fen`, but only checks the syntax.  Meant for positions reached in play,
+    /// which `validate` may not accept, e.g. once an army has been left without an owner.
+    pub fn try_from_fen_unvalidated(fen: &str) -> Result<Self, FenError> {
+        let (board, active_player, p1_owned, p1_controlled, p2_owned, p2_controlled, ply) =
+            Fen::try_parse(fen)?;
+        Ok(Position {
+            board,
+            active_player,
+            p1_owned,
+            p1_controlled,
+            p2_owned,
+            p2_controlled,
+            ply,
+        })
+    }
+
+    /// Inconsistencies that would otherwise only show up as a panic later on, e.g. in
+    /// `defect_to`.  Armies must be owned or controlled by at most one player, and only owned
+    /// armies have a King.  Before the first move, White and Black each need a King, see
+    /// `accept_first_move`.
+    pub fn validate(&self) -> Vec<PositionProblem> {
+        let mut problems = Vec::new();
+
+        let owned: Vec<Color> = match (self.p1_owned, self.p2_owned) {
+            (Some(p1), Some(p2)) if p1 == p2 => {
+                problems.push(PositionProblem::OwnedByBoth(p1));
+                vec![p1]
+            }
+            (Some(p1), Some(p2)) => vec![p1, p2],
+            (None, None) => vec![Color::White, Color::Black],
+            (Some(owned), None) | (None, Some(owned)) => {
+                problems.push(PositionProblem::OneOwner);
+                vec![owned]
+            }
+        };
+
+        for color in Color::all() {
+            let is_owned = [self.p1_owned, self.p2_owned].contains(&Some(color));
+            let p1_controls = self.p1_controlled.contains(&color);
+            let p2_controls = self.p2_controlled.contains(&color);
+            if is_owned && (p1_controls || p2_controls) {
+                problems.push(PositionProblem::OwnedAndControlled(color));
+            }
+            if p1_controls && p2_controls {
+                problems.push(PositionProblem::ControlledByBoth(color));
+            }
+
+            let kings = self
+                .board
+                .by_square
+                .values()
+                .filter(|p| p.color == color && p.role == Role::King)
+                .count();
+            match (owned.contains(&color), kings) {
+                (true, 0) => problems.push(PositionProblem::MissingKing(color)),
+                (false, 1..) => problems.push(PositionProblem::UnownedKing(color)),
+                _ => {}
+            }
+            if kings > 1 {
+                problems.push(PositionProblem::ExtraKing(color));
+            }
+        }
+
+        problems
+    }
+
+    pub fn from_fen(fen: String) -> Self {
+        let (board, active_player, p1_owned, p1_controlled, p2_owned, p2_controlled, ply) =
+            Fen::parse(&fen);
+
+        Position {
+            board: board,
+            active_player: active_player,
+            p1_owned: p1_owned,
+            p1_controlled: p1_controlled,
+            p2_owned: p2_owned,
+            p2_controlled: p2_controlled,
+            ply,
+        }
+    }
+
+    pub fn to_fen(&self) -> String {
+        let p1_owned = if let Some(v) = &self.p1_owned {
+            v.to_char().clone()
+        } else {
+            '-'
+        };
+
+        let p2_owned = if let Some(v) = &self.p2_owned {
+            v.to_char().clone()
+        } else {
+            '-'
+        };
+
+        let mut p1_controlled = self
+            .p1_controlled
+            .iter()
+            .map(|x| x.to_char().clone())
+            .collect::<Vec<char>>();
+        let mut p2_controlled = self
+            .p2_controlled
+            .iter()
+            .map(|x| x.to_char().clone())
+            .collect::<Vec<char>>();
+        let p1_controlled_computed = if p1_controlled.len() > 0 {
+            p1_controlled.sort_unstable();
+            p1_controlled.into_iter().collect::<String>()
+        } else {
+            "-".to_string()
+        };
+        let p2_controlled_computed = if p2_controlled.len() > 0 {
+            p2_controlled.sort_unstable();
+            p2_controlled.into_iter().collect::<String>()
+        } else {
+            "-".to_string()
+        };
+
+        format!(
+            "{} {} {} {} {} {} {}",
+            Fen::from_board(&self.board),
+            self.active_player.to_int(),
+            p1_owned,
+            p1_controlled_computed,
+            p2_owned,
+            p2_controlled_computed,
+            self.ply,
+        )
+        .to_string()
+    }
+
+    pub fn active_player(&self) -> u8 {
+        self.active_player.to_int()
+    }
+
+    pub fn p1_owned(&self) -> Option<Color> {
+        self.p1_owned
+    }
+
+    pub fn ply(&self) -> u32 {
+        self.ply
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// All moves the active player can make, ordered by origin then destination square.
+    /// Defections are not included.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let movable_colors = if self.p1_owned.is_none() {
+            // First move of the game must be a white piece
+            HashSet::from([Color::White])
+        } else {
+            self.player_colors(&self.active_player)
+        };
+        let (own_side, other_side) = self.sides(&self.active_player);
+
+        let mut moves = Vec::new();
+        for (from, piece) in &self.board.by_square {
+            if !movable_colors.contains(&piece.color) {
+                continue;
+            }
+
+            let destinations = self
+                .board
+                .compute_legal_moves(from, piece, &own_side, &other_side);
+            for index in destinations.iter_ones() {
+                moves.push(Move {
+                    color: piece.color,
+                    role: piece.role.clone(),
+                    from: *from,
+                    to: Square::from_index(index),
+                    promotion: None,
+                });
+            }
+        }
+
+        moves.sort_by_key(|m| (m.from.to_index(), m.to.to_index()));
+        moves
+    }
+
+    /// Whether the active player's King can be captured by the opponent
+    pub fn is_check(&self) -> bool {
+        self.is_in_check(&self.active_player)
+    }
+
+    /// Whether the active player is in check and no action gets their King out of it, be it a
+    /// move, including any promotion, or a defection
+    pub fn is_checkmate(&self) -> bool {
+        if !self.is_check() {
+            return false;
+        }
+
+        let player = &self.active_player;
+        let escapes_by_move = self
+            .legal_moves()
+            .iter()
+            .any(|move_| self.move_escapes_check(move_, player));
+        let escapes_by_defection = self
+            .controlled(player.to_int())
+            .iter()
+            .any(|color| self.defection_escapes_check(*color, player));
+
+        !escapes_by_move && !escapes_by_defection
+    }
+
+    /// Whether `move_`, or the same pawn move with a promotion, leaves `player` out of check
+    fn move_escapes_check(&self, move_: &Move, player: &Player) -> bool {
+        let promotions = match move_.role {
+            Role::Pawn => vec![
+                None,
+                Some(Role::Knight),
+                Some(Role::Bishop),
+                Some(Role::Rook),
+                Some(Role::Queen),
+                Some(Role::King),
+            ],
+            _ => vec![None],
+        };
+
+        promotions.into_iter().any(|promotion| {
+            let move_ = Move {
+                promotion,
+                ..move_.clone()
+            };
+            let mut pos = self.clone();
+            pos.play_move(&move_).is_ok() && !pos.is_in_check(player)
+        })
+    }
+
+    /// Whether defecting to `color` leaves `player` out of check, counting the King move that
+    /// has to follow if the King ends up on a square of its new color
+    fn defection_escapes_check(&self, color: Color, player: &Player) -> bool {
+        let mut pos = self.clone();
+        match pos.defect_to(color) {
+            Ok(()) => !pos.is_in_check(player),
+            Err(PositionError::DefectMoveKing) => pos.legal_moves().iter().any(|move_| {
+                let mut after = pos.clone();
+                after.play_move_after_defect(move_).is_ok() && !after.is_in_check(player)
+            }),
+            Err(_) => false,
+        }
+    }
+
+    fn is_in_check(&self, player: &Player) -> bool {
+        let owned = match player {
+            Player::P1 => self.p1_owned,
+            Player::P2 => self.p2_owned,
+        };
+        let Some(king_square) = owned.and_then(|color| self.board.find_king(&color)) else {
+            return false;
+        };
+
+        let attacker = player.next();
+        let attacker_colors = self.player_colors(&attacker);
+        let (own_side, other_side) = self.sides(&attacker);
+
+        self.board
+            .by_square
+            .iter()
+            .filter(|(_, piece)| attacker_colors.contains(&piece.color))
+            .any(|(from, piece)| {
+                self.board
+                    .compute_legal_moves(from, piece, &own_side, &other_side)
+                    .get(king_square.to_index())
+                    .unwrap()
+            })
+    }
+
+    /// The owned army plus all controlled armies of player 1 or 2
+    pub fn armies(&self, player: u8) -> HashSet<Color> {
+        match player {
+            1 => self.player_colors(&Player::P1),
+            _ => self.player_colors(&Player::P2),
+        }
+    }
+
+    /// The army player 1 or 2 owns, if armies have been assigned yet
+    pub fn owned(&self, player: u8) -> Option<Color> {
+        match player {
+            1 => self.p1_owned,
+            _ => self.p2_owned,
+        }
+    }
+
+    /// The armies player 1 or 2 controls but does not own, i.e. the ones they can defect to
+    pub fn controlled(&self, player: u8) -> &HashSet<Color> {
+        match player {
+            1 => &self.p1_controlled,
+            _ => &self.p2_controlled,
+        }
+    }
+
+    /// The owned army plus all controlled armies of `player`
+    fn player_colors(&self, player: &Player) -> HashSet<Color> {
+        let (owned, controlled) = match player {
+            Player::P1 => (&self.p1_owned, &self.p1_controlled),
+            Player::P2 => (&self.p2_owned, &self.p2_controlled),
+        };
+
+        let mut colors = controlled.clone();
+        if let Some(c) = owned {
+            colors.insert(*c);
+        }
+        colors
+    }
+
+    /// Split the armies into the ones `player` cannot capture and the ones they can.
+    /// Any colors not controlled by the opponent effectively are our own side.
+    fn sides(&self, player: &Player) -> (HashSet<Color>, HashSet<Color>) {
+        let other_side = self.player_colors(&player.next());
+        let own_side = HashSet::from(Color::all())
+            .difference(&other_side)
+            .cloned()
+            .collect();
+        (own_side, other_side)
+    }
+
+    pub fn play_move_after_defect(&mut self, move_: &Move) -> Result<&Self, PositionError> {
+        if move_.role != Role::King {
+            return Err(PositionError::DefectMoveKing);
+        }
+
+        self.play_move(move_)
+    }
+
+    pub fn play_move(&mut self, new_move: &Move) -> Result<&Self, PositionError> {
+        if self.p1_owned.is_none() {
+            // Must be the first move of the game,
+            // thus it must be a white piece that is moved.
+            if new_move.color != Color::White {
+                return Err(PositionError::FirstMoveNotWhite);
+            }
+        } else if !self.does_color_belong_to_user(&new_move.color) {
+            // Check if user owns or controls this army
+            return Err(PositionError::NotOwnColor);
+        }
+
+        // Collect any colors not controlled by the opponent
+        // This effectively is our own side, meaning, we can't capture
+        // these color pieces.
+        let (own_side, other_side) = self.sides(&self.active_player);
+
+        if !self.board.is_legal_move(&new_move, &own_side, &other_side) {
+            return Err(PositionError::IllegalMove);
+        }
+
+        self.update_board(new_move);
+
+        self.update_controlled_armies(new_move);
+
+        // Make sure we update the `active_player` after we're done updating the board, since the
+        // logic is dependent on this field.
+        self.active_player = self.active_player.next();
+        self.ply += 1;
+        Ok(self)
+    }
+
+    fn update_board(&mut self, move_: &Move) {
+        self.board.remove_piece_at(&move_.from);
+        // Captured piece, if any
+        self.board.remove_piece_at(&move_.to);
+
+        let role = if let Some(role) = &move_.promotion {
+            if *role == Role::King {
+                match self.active_player {
+                    Player::P1 => {
+                        // Remove existing King since we're promoting to a new King
+                        self.board.remove_piece(Piece::new(
+                            self.p1_owned.expect("p1_owned should not be None"),
+                            Role::King,
+                        ));
+
+                        // If King is a different color, update our owned an controlled armies
+                        self.p1_owned = Some(move_.color.clone());
+                        self.p1_controlled.remove(&move_.color);
+                    }
+                    Player::P2 => {
+                        // Remove existing King since we're promoting to a new King
+                        self.board.remove_piece(Piece::new(
+                            self.p2_owned.expect("p2_owned should not be None"),
+                            Role::King,
+                        ));
+
+                        // If King is a different color, update our owned an controlled armies
+                        self.p2_owned = Some(move_.color.clone());
+                        self.p2_controlled.remove(&move_.color);
+                    }
+                }
+            }
+
+            role
+        } else {
+            &move_.role
+        };
+
+        let piece = Piece {
+            color: move_.color.clone(),
+            role: role.clone(),
+        };
+
+        self.board.insert_piece(move_.to.clone(), piece);
+    }
+
+    pub fn accept_first_move(&mut self) -> &Self {
+        self.active_player = Player::P1;
+        self.p1_owned = Some(Color::Black);
+        self.p2_owned = Some(Color::White);
+
+        self
+    }
+
+    pub fn reject_first_move(&mut self) -> &Self {
+        self.active_player = Player::P2;
+        self.p1_owned = Some(Color::White);
+        self.p2_owned = Some(Color::Black);
+
+        self
+    }
+
+    fn does_color_belong_to_user(&self, color: &Color) -> bool {
+        match self.active_player {
+            Player::P1 => {
+                if *color == self.p1_owned.clone().unwrap() {
+                    true
+                } else if self.p1_controlled.contains(color) {
+                    true
+                } else {
+                    false
+                }
+            }
+            Player::P2 => {
+                if *color == self.p2_owned.clone().unwrap() {
+                    true
+                } else if self.p2_controlled.contains(color) {
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    fn update_controlled_armies(&mut self, move_: &Move) {
+        match self.active_player {
+            Player::P1 => {
+                if let Some(color) = move_.from.color() {
+                    self.p1_controlled.remove(&color);
+                }
+                if let Some(color) = move_.to.color() {
+                    if Some(color) != self.p2_owned {
+                        self.p1_controlled.insert(color);
+                    }
+                }
+            }
+            Player::P2 => {
+                if let Some(color) = move_.from.color() {
+                    self.p2_controlled.remove(&color);
+                }
+                if let Some(color) = move_.to.color() {
+                    if Some(color) != self.p1_owned {
+                        self.p2_controlled.insert(color);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn defect_to(&mut self, color: Color) -> Result<(), PositionError> {
+        fn is_king_on_own_color_square(piece: &Piece, sq: &Square) -> bool {
+            sq.color() == Some(piece.color)
+        }
+
+        match self.active_player {
+            Player::P1 => {
+                if !self.p1_controlled.contains(&color) {
+                    return Err(PositionError::IllegalMove);
+                }
+
+                // Swap King
+                let piece = Piece::new(self.p1_owned.unwrap(), Role::King);
+                let square = self.board.find(&piece).unwrap();
+                self.board.remove_piece(piece.clone());
+
+                let new_piece = Piece::new(color, Role::King);
+                self.board.insert_piece(square.clone(), new_piece.clone());
+
+                // Update armies
+                self.p1_controlled.remove(&color);
+                self.p1_owned = Some(color);
+
+                if is_king_on_own_color_square(&new_piece, &square) {
+                    return Err(PositionError::DefectMoveKing);
+                }
+            }
+            Player::P2 => {
+                if !self.p2_controlled.contains(&color) {
+                    return Err(PositionError::IllegalMove);
+                }
+
+                // Swap King
+                let piece = Piece::new(self.p2_owned.unwrap(), Role::King);
+                let square = self.board.find(&piece).unwrap();
+                self.board.remove_piece(piece.clone());
+
+                let new_piece = Piece::new(color, Role::King);
+                self.board.insert_piece(square.clone(), new_piece.clone());
+
+                // Update armies
+                self.p2_controlled.remove(&color);
+                self.p2_owned = Some(color);
+
+                if is_king_on_own_color_square(&new_piece, &square) {
+                    return Err(PositionError::DefectMoveKing);
+                }
+            }
+        }
+
+        self.active_player = self.active_player.next();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_position_eq;
+
+    #[test]
+    fn from_fen_works() {
+        assert_position_eq!(
+            Position::from_fen(Position::new_fen()),
+            Position {
+                board: Fen::to_board(INITIAL_FEN.split(' ').next().unwrap()),
+                active_player: Player::P1,
+                p1_owned: None,
+                p1_controlled: HashSet::new(),
+                p2_owned: None,
+                p2_controlled: HashSet::new(),
+                ply: 0,
+            },
+        );
+    }
+
+    #[test]
+    fn try_from_fen_works() {
+        assert_position_eq!(
+            Position::try_from_fen(INITIAL_FEN).unwrap(),
+            Position::new()
+        );
+
+        let board = "bk15/16/16/16/16/16/16/16/16/16/16/16/16/16/16/wk15";
+        let pos = |fields: &str| Position::try_from_fen(&format!("{} {}", board, fields));
+        assert!(pos("2 w g b n 7").is_ok());
+        assert!(pos("1 w - - - 0").is_err());
+        assert_eq!(
+            pos("3 w - b - 0").unwrap_err(),
+            FenError::InvalidActivePlayer
+        );
+    }
+
+    #[test]
+    fn validate_works() {
+        let board = "bk15/16/16/16/16/16/16/16/16/16/16/16/16/16/16/wk15";
+        let pos = |fields: &str| Position::from_fen(format!("{} {}", board, fields));
+        assert!(pos("2 w g b n 7").validate().is_empty());
+        assert!(pos("1 - - - - 0").validate().is_empty());
+
+        assert_eq!(
+            pos("1 w - - - 0").validate(),
+            vec![
+                PositionProblem::OneOwner,
+                PositionProblem::UnownedKing(Color::Black)
+            ],
+        );
+        assert_eq!(
+            pos("1 w - w - 0").validate(),
+            vec![
+                PositionProblem::OwnedByBoth(Color::White),
+                PositionProblem::UnownedKing(Color::Black)
+            ],
+        );
+        assert_eq!(
+            pos("1 w g b g 0").validate(),
+            vec![PositionProblem::ControlledByBoth(Color::Green)],
+        );
+        assert_eq!(
+            pos("1 w b b - 0").validate(),
+            vec![PositionProblem::OwnedAndControlled(Color::Black)],
+        );
+        assert_eq!(
+            pos("1 w - n - 0").validate(),
+            vec![
+                PositionProblem::UnownedKing(Color::Black),
+                PositionProblem::MissingKing(Color::Navy)
+            ],
+        );
+
+        let fen = "bk15/16/16/16/16/16/16/16/16/16/16/16/16/16/16/wkwk14 1 w - b - 0";
+        assert_eq!(
+            Position::from_fen(fen.to_string()).validate(),
+            vec![PositionProblem::ExtraKing(Color::White)],
+        );
+    }
+
+    #[test]
+    fn to_fen_works() {
+        assert_eq!(
+            Position {
+                board: Fen::to_board(INITIAL_FEN.split(' ').next().unwrap()),
+                active_player: Player::P1,
+                p1_owned: None,
+                p1_controlled: HashSet::new(),
+                p2_owned: None,
+                p2_controlled: HashSet::new(),
+                ply: 0,
+            }
+            .to_fen(),
+            Position::new_fen(),
+        );
+
+        assert_eq!(
+            Position {
+                board: Fen::to_board(INITIAL_FEN.split(' ').next().unwrap()),
+                active_player: Player::P1,
+                p1_owned: Some(Color::White),
+                p1_controlled: HashSet::from([Color::Green]),
+                p2_owned: Some(Color::Black),
+                p2_controlled: HashSet::from([Color::Yellow, Color::Pink]),
+                ply: 0,
+            }.to_fen(),
+            "aqabvrvnbrbnbbbqbkbbbnbrynyrsbsq/aranvpvpbpbpbpbpbpbpbpbpypypsnsr/nbnp12opob/nqnp12opoq/crcp12rprr/cncp12rprn/gbgp12pppb/gqgp12pppq/yqyp12vpvq/ybyp12vpvb/onop12npnn/orop12npnr/rqrp12cpcq/rbrp12cpcb/srsnppppwpwpwpwpwpwpwpwpgpgpanar/sqsbprpnwrwnwbwqwkwbwnwrgngrabaq 1 w g b py 0".to_string(),
+        );
+    }
+
+    #[test]
+    fn display_works() {
+        let fen = "16/16/16/16/16/16/bk15/16/16/16/16/16/16/02wr13/16/wk15 1 w g b - 3";
+        let display = Position::from_fen(fen.to_string()).to_string();
+        let lines: Vec<&str> = display.lines().collect();
+
+        assert_eq!(lines.len(), 1 + 16 + 1 + 1);
+        assert_eq!(
+            lines[0],
+            "     a  b  c  d  e  f  g  h  i  j  k  l  m  n  o  p"
+        );
+        assert_eq!(
+            lines[1],
+            "16   .  .  .  .  .  .  .  .  .  .  .  .  .  .  .  . 16"
+        );
+        assert_eq!(
+            lines[12],
+            " 5   .  .  .  .  n  .  .  .  .  .  .  r  .  .  .  .  5"
+        );
+        assert_eq!(
+            lines[14],
+            " 3   .  . wR  .  .  .  .  .  .  .  .  .  .  .  .  .  3"
+        );
+        assert_eq!(
+            lines[18],
+            "Player 1 to move | Player 1 owns white, controls green | Player 2 owns black, controls none | Ply 3"
+        );
+    }
+
+    #[test]
+    fn play_move_updates_fields() {
+        let mut pos = Position::new();
+        let new_move = Move::from_san("WNf01g03");
+        let new_pos = pos.play_move(&new_move).unwrap();
+
+        assert_eq!(new_pos.active_player, Player::P2);
+        assert_eq!(new_pos.ply, 1);
+    }
+
+    #[test]
+    fn is_checkmate_counts_defections() {
+        // The White King on h8 is in check from the Navy Bishop and cannot move: the white
+        // squares h9 and i8 are off limits to it, the rest are covered
+        let board = "nk05nr09/16/16/16/16/16/16/16/07wk08/nr15/16/16/03nb12/16/16/16";
+        let pos = |fields: &str| Position::from_fen(format!("{} {}", board, fields));
+        assert!(pos("1 w - n - 0").is_checkmate());
+
+        // Defecting to Black leaves a Black King on a black square, which then escapes to h9
+        let pos = pos("1 w b n - 0");
+        assert!(pos.is_check());
+        assert!(pos.legal_moves().iter().all(|m| {
+            let mut after = pos.clone();
+            after.play_move(m).is_err() || after.is_in_check(&Player::P1)
+        }));
+        assert!(!pos.is_checkmate());
+    }
+
+    #[test]
+    fn defect_to_works() {
+        let fen =
+            String::from("08bk07/16/16/16/16/16/16/16/16/16/16/04wq11/16/16/16/08wk07 1 w n b - 0");
+        let mut pos = Position::from_fen(fen);
+
+        let _ = pos.defect_to(Color::Navy);
+
+        assert_eq!(pos.p1_owned, Some(Color::Navy));
+        assert!(pos.p1_controlled.is_empty());
+        assert_eq!(
+            pos.board.get(&Square::I1),
+            Some(&Piece::new(Color::Navy, Role::King))
+        );
+        assert_eq!(pos.active_player, Player::P2);
+    }
+
+    #[test]
+    fn defect_to_with_king_on_own_color_square_works() {
+        let fen =
+            String::from("08bk07/16/16/16/16/16/16/16/16/16/16/04wk11/16/16/16/16 1 w n b - 0");
+        let mut pos = Position::from_fen(fen);
+
+        let result = pos.defect_to(Color::Navy);
+
+        assert!(result.is_err_and(|e| e == PositionError::DefectMoveKing));
+        assert_eq!(pos.p1_owned, Some(Color::Navy));
+        assert!(pos.p1_controlled.is_empty());
+        assert_eq!(
+            pos.board.get(&Square::E5),
+            Some(&Piece::new(Color::Navy, Role::King))
+        );
+        assert_eq!(pos.active_player, Player::P1);
+    }
+}
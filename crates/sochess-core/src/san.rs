@@ -0,0 +1,348 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::{Color, Move, Position, Role, Square};
+
+#[derive(Debug, PartialEq)]
+pub enum SanAction {
+    Move(Move),
+    Defect(Color),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SanError {
+    InvalidNotation,
+    IllegalMove,
+    /// More than one piece can make the move, the origin square is needed
+    AmbiguousMove,
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            SanError::InvalidNotation => "invalid notation",
+            SanError::IllegalMove => "illegal move",
+            SanError::AmbiguousMove => "ambiguous move, the origin square is needed",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl Error for SanError {}
+
+/**
+ * Short Algebraic Notation - the human-readable counterpart to `Move::from_san`
+ * Format:
+ * - Army color in lowercase, then role in uppercase (omitted for pawns).  e.g. `wNc3`
+ * - Origin file, rank or both, only when another piece of the same army and role
+ *   can reach the same square.  e.g. `wRce3`, `wR3c5`, `wRc3c5`
+ * - `x` for captures.  Pawn captures always include the origin file.  e.g. `whxi7`
+ * - `=` and a role for promotions.  e.g. `wj8=Q`
+ * - `+` for check, `#` for checkmate
+ * - Defections are notated as `D:` followed by the army name.  e.g. `D:navy`
+ */
+pub struct San {}
+
+impl San {
+    /// Notate `move_`, which must be legal in `pos`.
+    pub fn from_move(pos: &Position, move_: &Move) -> String {
+        San::from_legal_move(pos, move_, &pos.legal_moves())
+    }
+
+    /// Same as `from_move`, with `legal_moves` being `pos.legal_moves()`.  Saves generating
+    /// them again for each move when notating many moves from one position.
+    pub fn from_legal_move(pos: &Position, move_: &Move, legal_moves: &[Move]) -> String {
+        let is_capture = pos.board().get(&move_.to).is_some();
+
+        let mut san = String::new();
+        san.push(move_.color.to_char());
+        if move_.role != Role::Pawn {
+            san.push(move_.role.to_char().to_ascii_uppercase());
+        }
+        san.push_str(&San::disambiguation(legal_moves, move_, is_capture));
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&move_.to.to_short_str());
+        if let Some(role) = &move_.promotion {
+            san.push('=');
+            san.push(role.to_char().to_ascii_uppercase());
+        }
+
+        let mut new_pos = pos.clone();
+        if new_pos.play_move(move_).is_ok() {
+            if new_pos.is_checkmate() {
+                san.push('#');
+            } else if new_pos.is_check() {
+                san.push('+');
+            }
+        }
+
+        san
+    }
+
+    pub fn from_defect(color: &Color) -> String {
+        format!("D:{}", color.to_name())
+    }
+
+    pub fn parse(pos: &Position, san: &str) -> Result<SanAction, SanError> {
+        if let Some(name) = san.strip_prefix("D:") {
+            return Color::all()
+                .into_iter()
+                .find(|c| c.to_name() == name.to_lowercase())
+                .map(SanAction::Defect)
+                .ok_or(SanError::InvalidNotation);
+        }
+
+        let san = san.trim_end_matches(['+', '#']);
+        let (san, promotion) = match san.split_once('=') {
+            Some((rest, role)) => {
+                let mut chars = role.chars();
+                let promotion = chars
+                    .next()
+                    .and_then(|c| Role::from_char(c.to_ascii_lowercase()))
+                    .ok_or(SanError::InvalidNotation)?;
+                if chars.next().is_some() {
+                    return Err(SanError::InvalidNotation);
+                }
+                (rest, Some(promotion))
+            }
+            None => (san, None),
+        };
+
+        let mut chars = san.chars().peekable();
+        let color = chars
+            .next()
+            .and_then(Color::from_char)
+            .ok_or(SanError::InvalidNotation)?;
+        let role = match chars.peek() {
+            Some(c) if c.is_ascii_uppercase() => {
+                let role =
+                    Role::from_char(c.to_ascii_lowercase()).ok_or(SanError::InvalidNotation)?;
+                chars.next();
+                role
+            }
+            _ => Role::Pawn,
+        };
+        if promotion.is_some() && role != Role::Pawn {
+            return Err(SanError::InvalidNotation);
+        }
+
+        // The destination square is the last file letter and the rank digits following it
+        let rest: String = chars.collect();
+        let to_start = rest
+            .rfind(|c: char| c.is_ascii_alphabetic())
+            .ok_or(SanError::InvalidNotation)?;
+        let to = Square::from_short_str(&rest[to_start..]).ok_or(SanError::InvalidNotation)?;
+        let prefix = &rest[..to_start];
+        let prefix = prefix.strip_suffix('x').unwrap_or(prefix);
+        let (from_file, from_rank) = San::parse_disambiguation(prefix)?;
+
+        let mut candidates = pos.legal_moves().into_iter().filter(|m| {
+            m.color == color
+                && m.role == role
+                && m.to == to
+                && (from_file.is_none() || from_file == Some(m.from.file().to_index()))
+                && (from_rank.is_none() || from_rank == Some(m.from.rank_index()))
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(mut move_), None) => {
+                move_.promotion = promotion;
+                Ok(SanAction::Move(move_))
+            }
+            (Some(_), Some(_)) => Err(SanError::AmbiguousMove),
+            (None, _) => Err(SanError::IllegalMove),
+        }
+    }
+
+    /// Origin file and rank needed to tell `move_` apart from other legal moves
+    fn disambiguation(legal_moves: &[Move], move_: &Move, is_capture: bool) -> String {
+        let rivals: Vec<Square> = legal_moves
+            .iter()
+            .filter(|m| {
+                m.color == move_.color
+                    && m.role == move_.role
+                    && m.to == move_.to
+                    && m.from != move_.from
+            })
+            .map(|m| m.from)
+            .collect();
+        let is_pawn_capture = move_.role == Role::Pawn && is_capture;
+
+        if rivals.is_empty() && !is_pawn_capture {
+            return String::new();
+        }
+
+        let same_file = rivals.iter().any(|sq| sq.file() == move_.from.file());
+        let same_rank = rivals
+            .iter()
+            .any(|sq| sq.rank_index() == move_.from.rank_index());
+
+        if !same_file {
+            move_.from.file().to_str().to_string()
+        } else if !same_rank && !is_pawn_capture {
+            (move_.from.rank_index() + 1).to_string()
+        } else {
+            move_.from.to_short_str()
+        }
+    }
+
+    /// Parse an optional origin file followed by an optional origin rank, as indices
+    fn parse_disambiguation(s: &str) -> Result<(Option<usize>, Option<usize>), SanError> {
+        let (file_id, rank_id) = match s.chars().next() {
+            Some(c) if c.is_ascii_alphabetic() => s.split_at(1),
+            _ => ("", s),
+        };
+
+        let file = if file_id.is_empty() {
+            None
+        } else {
+            Some(
+                Square::from_short_str(&format!("{}1", file_id))
+                    .ok_or(SanError::InvalidNotation)?
+                    .file()
+                    .to_index(),
+            )
+        };
+
+        let rank = if rank_id.is_empty() {
+            None
+        } else {
+            Some(
+                Square::from_short_str(&format!("a{}", rank_id))
+                    .ok_or(SanError::InvalidNotation)?
+                    .rank_index(),
+            )
+        };
+
+        Ok((file, rank))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // White rooks on c3 and g3
+    const TWO_ROOKS_FEN: &str =
+        "16/16/16/16/16/16/bk15/16/16/16/16/16/16/02wr03wr09/16/wk15 1 w - b - 0";
+
+    #[test]
+    fn from_move_works() {
+        let pos = Position::new();
+        assert_eq!(
+            San::from_move(&pos, &Move::from_san("WNf01g03")),
+            "wNg3".to_string(),
+        );
+        assert_eq!(
+            San::from_move(&pos, &Move::from_san("WPi02i04")),
+            "wi4".to_string(),
+        );
+    }
+
+    #[test]
+    fn from_move_with_disambiguation_works() {
+        let pos = Position::from_fen(TWO_ROOKS_FEN.to_string());
+        assert_eq!(
+            San::from_move(&pos, &Move::from_san("WRc03e03")),
+            "wRce3".to_string(),
+        );
+
+        let fen = "bk15/16/16/16/16/16/16/16/16/02wr13/16/16/16/02wr13/16/wk15 1 w - b - 0";
+        let pos = Position::from_fen(fen.to_string());
+        assert_eq!(
+            San::from_move(&pos, &Move::from_san("WRc03c05")),
+            "wR3c5".to_string(),
+        );
+    }
+
+    #[test]
+    fn from_legal_move_works() {
+        let pos = Position::from_fen(TWO_ROOKS_FEN.to_string());
+        let legal_moves = pos.legal_moves();
+        for move_ in &legal_moves {
+            assert_eq!(
+                San::from_legal_move(&pos, move_, &legal_moves),
+                San::from_move(&pos, move_),
+            );
+        }
+    }
+
+    #[test]
+    fn from_move_with_check_and_mate_works() {
+        let pos = Position::from_fen(TWO_ROOKS_FEN.to_string());
+        assert_eq!(
+            San::from_move(&pos, &Move::from_san("WRc03a03")),
+            "wRa3+".to_string(),
+        );
+
+        // Rook on b5 covers the King's escape squares
+        let fen = "16/16/16/16/16/16/bk15/16/16/16/16/01wr14/16/02wr13/16/wk15 1 w - b - 0";
+        let pos = Position::from_fen(fen.to_string());
+        assert_eq!(
+            San::from_move(&pos, &Move::from_san("WRc03a03")),
+            "wRa3#".to_string(),
+        );
+    }
+
+    #[test]
+    fn from_move_with_promotion_works() {
+        let fen = "bk15/16/16/16/16/16/16/16/16/09wp06/16/16/16/16/16/wk15 1 w - b - 0";
+        let pos = Position::from_fen(fen.to_string());
+        assert_eq!(
+            San::from_move(&pos, &Move::from_san("WPj07j08=Q")),
+            "wj8=Q".to_string(),
+        );
+    }
+
+    #[test]
+    fn parse_works() {
+        let pos = Position::new();
+        assert_eq!(
+            San::parse(&pos, "wNg3"),
+            Ok(SanAction::Move(Move::from_san("WNf01g03"))),
+        );
+
+        let pos = Position::from_fen(TWO_ROOKS_FEN.to_string());
+        assert_eq!(
+            San::parse(&pos, "wRce3"),
+            Ok(SanAction::Move(Move::from_san("WRc03e03"))),
+        );
+        assert_eq!(
+            San::parse(&pos, "wRc3e3"),
+            Ok(SanAction::Move(Move::from_san("WRc03e03"))),
+        );
+        assert_eq!(
+            San::parse(&pos, "wRa3+"),
+            Ok(SanAction::Move(Move::from_san("WRc03a03"))),
+        );
+        assert_eq!(San::parse(&pos, "wRe3"), Err(SanError::AmbiguousMove));
+        assert_eq!(San::parse(&pos, "wRe4"), Err(SanError::IllegalMove));
+        assert_eq!(San::parse(&pos, "wZe3"), Err(SanError::InvalidNotation));
+        assert_eq!(San::parse(&pos, "wRq3"), Err(SanError::InvalidNotation));
+    }
+
+    #[test]
+    fn parse_with_promotion_works() {
+        let fen = "bk15/16/16/16/16/16/16/16/16/09wp06/16/16/16/16/16/wk15 1 w - b - 0";
+        let pos = Position::from_fen(fen.to_string());
+        assert_eq!(
+            San::parse(&pos, "wj8=Q"),
+            Ok(SanAction::Move(Move::from_san("WPj07j08=Q"))),
+        );
+        assert_eq!(San::parse(&pos, "wKb1=Q"), Err(SanError::InvalidNotation));
+    }
+
+    #[test]
+    fn defect_works() {
+        let pos = Position::new();
+        assert_eq!(San::from_defect(&Color::Navy), "D:navy".to_string());
+        assert_eq!(
+            San::parse(&pos, "D:navy"),
+            Ok(SanAction::Defect(Color::Navy))
+        );
+        assert_eq!(San::parse(&pos, "D:teal"), Err(SanError::InvalidNotation));
+    }
+}
@@ -0,0 +1,108 @@
+//! Compact representations for the `serde` feature, matching the notation used elsewhere:
+//! squares as `"i8"`, pieces as `"wq"` and positions as FEN.  Colors and roles use their
+//! lowercase names, and moves are structs of the above.
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+use crate::{Color, Piece, Position, Role, Square};
+
+impl Serialize for Square {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_short_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Square {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Square::from_short_str(&s)
+            .ok_or_else(|| de::Error::custom(format!("invalid square `{}`", s)))
+    }
+}
+
+impl Serialize for Piece {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Piece {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let mut chars = s.chars();
+        let piece = match (chars.next(), chars.next(), chars.next()) {
+            (Some(color), Some(role), None) => Color::from_char(color).zip(Role::from_char(role)),
+            _ => None,
+        };
+        piece
+            .map(|(color, role)| Piece::new(color, role))
+            .ok_or_else(|| de::Error::custom(format!("invalid piece `{}`", s)))
+    }
+}
+
+impl Serialize for Position {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_fen())
+    }
+}
+
+impl<'de> Deserialize<'de> for Position {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Position::try_from_fen(&s).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Move;
+
+    #[test]
+    fn square_round_trips() {
+        assert_eq!(serde_json::to_string(&Square::I8).unwrap(), "\"i8\"");
+        assert_eq!(
+            serde_json::from_str::<Square>("\"p16\"").unwrap(),
+            Square::P16
+        );
+        assert!(serde_json::from_str::<Square>("\"q1\"").is_err());
+    }
+
+    #[test]
+    fn piece_round_trips() {
+        let piece = Piece::new(Color::White, Role::Queen);
+        assert_eq!(serde_json::to_string(&piece).unwrap(), "\"wq\"");
+        assert_eq!(serde_json::from_str::<Piece>("\"wq\"").unwrap(), piece);
+        assert!(serde_json::from_str::<Piece>("\"wqq\"").is_err());
+        assert!(serde_json::from_str::<Piece>("\"xq\"").is_err());
+    }
+
+    #[test]
+    fn move_round_trips() {
+        let move_ = Move::new(Color::White, Role::Knight, Square::F1, Square::G3);
+        let json = serde_json::to_string(&move_).unwrap();
+        assert_eq!(
+            json,
+            r#"{"color":"white","role":"knight","from":"f1","to":"g3"}"#
+        );
+        assert_eq!(serde_json::from_str::<Move>(&json).unwrap(), move_);
+
+        let promotion =
+            r#"{"color":"black","role":"pawn","from":"h3","to":"h2","promotion":"queen"}"#;
+        let move_ = serde_json::from_str::<Move>(promotion).unwrap();
+        assert_eq!(move_.promotion, Some(Role::Queen));
+        assert_eq!(serde_json::to_string(&move_).unwrap(), promotion);
+    }
+
+    #[test]
+    fn position_round_trips() {
+        let json = serde_json::to_string(&Position::new()).unwrap();
+        assert_eq!(json, format!("\"{}\"", Position::new_fen()));
+        assert_eq!(
+            serde_json::from_str::<Position>(&json).unwrap(),
+            Position::new()
+        );
+        assert!(serde_json::from_str::<Position>("\"16/16\"").is_err());
+    }
+}
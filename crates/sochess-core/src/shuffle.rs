@@ -0,0 +1,163 @@
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashSet;
+
+use crate::{Board, Color, Fen, Piece, Position, Role, Square, BOARD_SIZE};
+
+/// Files of the White and Black back ranks, `e` through `l`
+const BACK_RANK_FILES: std::ops::Range<usize> = 4..12;
+
+/**
+ * Chess960-style starting positions, reproducible from a seed
+ *
+ * - White's back rank is shuffled like in Chess960: the King between the Rooks and the Bishops
+ *   on squares of opposite shades.  Black mirrors White, file for file.
+ * - Every other army keeps its piece squares but shuffles the pieces among them.  The board
+ *   stays symmetric: the army whose pieces sit on the squares rotated by 180 degrees, e.g.
+ *   Green for Violet, or Ash for itself, gets the same arrangement.
+ *
+ * Pawns stay where they are, and nobody owns an army yet, so the game starts with the usual
+ * first move.  Pieces never end up on a colored square of their own color, since none of the
+ * shuffled squares is colored.
+ */
+pub struct Shuffle {}
+
+impl Shuffle {
+    pub fn fen(seed: u32) -> String {
+        // ChaCha keeps the same sequence across versions of `rand`, unlike `StdRng`
+        let mut rng = ChaCha8Rng::seed_from_u64(seed as u64);
+        let initial = Position::new();
+        let mut pieces = initial.board().by_square.clone();
+
+        for (file, role) in BACK_RANK_FILES.zip(Shuffle::back_rank(&mut rng)) {
+            for (rank, color) in [(0, Color::White), (15, Color::Black)] {
+                let square = Square::from_file_and_rank_index(file, rank);
+                pieces.insert(square, Piece::new(color, role.clone()));
+            }
+        }
+
+        let mut done = HashSet::new();
+        for color in Color::all() {
+            if color == Color::White || color == Color::Black {
+                continue;
+            }
+            let mut squares: Vec<Square> = initial
+                .board()
+                .by_square
+                .iter()
+                .filter(|(sq, p)| p.color == color && p.role != Role::Pawn && !done.contains(*sq))
+                .map(|(sq, _)| *sq)
+                .collect();
+            // Armies mapped onto themselves only shuffle one half
+            let own_squares: HashSet<Square> = squares.iter().copied().collect();
+            squares.retain(|sq| {
+                let rotated = Shuffle::rotate(sq);
+                !own_squares.contains(&rotated) || sq.to_index() < rotated.to_index()
+            });
+            squares.sort_by_key(|sq| sq.to_index());
+
+            let mut roles: Vec<Role> = squares.iter().map(|sq| pieces[sq].role.clone()).collect();
+            roles.shuffle(&mut rng);
+
+            for (square, role) in squares.into_iter().zip(roles) {
+                let rotated = Shuffle::rotate(&square);
+                let partner = pieces[&rotated].color;
+                pieces.insert(square, Piece::new(color, role.clone()));
+                pieces.insert(rotated, Piece::new(partner, role));
+                done.insert(square);
+                done.insert(rotated);
+            }
+        }
+
+        let mut board = Board::new();
+        for (square, piece) in pieces {
+            board.insert_piece(square, piece);
+        }
+        format!("{} 1 - - - - 0", Fen::from_board(&board))
+    }
+
+    /// Roles from file `e` to `l`
+    fn back_rank(rng: &mut impl Rng) -> Vec<Role> {
+        let mut slots: Vec<Option<Role>> = vec![None; BACK_RANK_FILES.len()];
+
+        // Files alternate shades, so one Bishop goes on an even file and the other on an odd one
+        slots[2 * rng.gen_range(0..4)] = Some(Role::Bishop);
+        slots[2 * rng.gen_range(0..4) + 1] = Some(Role::Bishop);
+
+        for role in [Role::Queen, Role::Knight, Role::Knight] {
+            let empty: Vec<usize> = (0..slots.len()).filter(|&i| slots[i].is_none()).collect();
+            slots[*empty.choose(rng).unwrap()] = Some(role);
+        }
+
+        // The three squares left are, in order, Rook, King and Rook
+        let mut rest = [Role::Rook, Role::King, Role::Rook].into_iter();
+        slots
+            .into_iter()
+            .map(|slot| slot.unwrap_or_else(|| rest.next().unwrap()))
+            .collect()
+    }
+
+    /// The square across the center of the board
+    fn rotate(square: &Square) -> Square {
+        Square::from_index(BOARD_SIZE - 1 - square.to_index())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_position_eq;
+    use std::collections::HashMap;
+
+    fn piece_counts(pos: &Position) -> HashMap<Piece, usize> {
+        let mut counts = HashMap::new();
+        for piece in pos.board().by_square.values() {
+            *counts.entry(piece.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn fen_is_reproducible() {
+        assert_position_eq!(
+            Position::from_fen(Shuffle::fen(42)),
+            Position::from_fen(Shuffle::fen(42))
+        );
+        assert!((0..10).any(|seed| Shuffle::fen(seed) != Shuffle::fen(42)));
+    }
+
+    #[test]
+    fn fen_is_valid() {
+        let initial = Position::new();
+        for seed in 0..50 {
+            let pos = Position::try_from_fen(&Shuffle::fen(seed)).unwrap();
+            let board = pos.board();
+            assert_eq!(piece_counts(&pos), piece_counts(&initial));
+
+            for (square, piece) in &board.by_square {
+                assert_ne!(square.color(), Some(piece.color));
+                let rotated = board.get(&Shuffle::rotate(square)).unwrap();
+                if piece.color != Color::White && piece.color != Color::Black {
+                    assert_eq!(rotated.role, piece.role);
+                }
+            }
+
+            let white: Vec<Role> = BACK_RANK_FILES
+                .map(|file| board.get(&Square::from_file_and_rank_index(file, 0)))
+                .map(|piece| piece.unwrap().role.clone())
+                .collect();
+            let black: Vec<Role> = BACK_RANK_FILES
+                .map(|file| board.get(&Square::from_file_and_rank_index(file, 15)))
+                .map(|piece| piece.unwrap().role.clone())
+                .collect();
+            assert_eq!(white, black);
+
+            let find = |role: Role| white.iter().position(|r| *r == role).unwrap();
+            let rooks: Vec<usize> = (0..8).filter(|&i| white[i] == Role::Rook).collect();
+            assert!(rooks[0] < find(Role::King) && find(Role::King) < rooks[1]);
+            let bishops: Vec<usize> = (0..8).filter(|&i| white[i] == Role::Bishop).collect();
+            assert_ne!(bishops[0] % 2, bishops[1] % 2);
+        }
+    }
+}
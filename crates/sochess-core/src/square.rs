@@ -1,4 +1,4 @@
-use crate::chessops::{Color, File, Rank, BOARD_SIZE, BOARD_WIDTH};
+use crate::{Color, File, Rank, BOARD_SIZE, BOARD_WIDTH};
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 #[repr(u16)]
@@ -267,6 +267,7 @@ impl Square {
     }
 
     // e.g. get Square::A1 from "A01"
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
         assert_eq!(s.len(), 3);
 
@@ -279,6 +280,33 @@ impl Square {
         ALL_SQUARES[index].clone()
     }
 
+    // e.g. get "a01" from Square::A1
+    pub fn to_str(self) -> String {
+        format!("{}{:02}", self.file().to_str(), self.rank_index() + 1)
+    }
+
+    // e.g. get Square::A1 from "a1".  Used by our short algebraic notation.
+    pub fn from_short_str(s: &str) -> Option<Self> {
+        if !s.is_char_boundary(1) {
+            return None;
+        }
+        let (file_id, rank_id) = s.split_at(1);
+        let file_index = File::iter().position(|f| f.to_str() == file_id)?;
+        if rank_id.starts_with('0') {
+            return None;
+        }
+        let rank: usize = rank_id.parse().ok()?;
+        if rank == 0 || rank > BOARD_WIDTH {
+            return None;
+        }
+        Some(Square::from_file_and_rank_index(file_index, rank - 1))
+    }
+
+    // e.g. get "a1" from Square::A1
+    pub fn to_short_str(self) -> String {
+        format!("{}{}", self.file().to_str(), self.rank_index() + 1)
+    }
+
     pub fn from_file_and_rank_index(file: usize, rank: usize) -> Self {
         let index = Square::calc_index(file, rank);
         ALL_SQUARES[index].clone()
@@ -297,6 +325,10 @@ impl Square {
         File::from_index(i % BOARD_WIDTH)
     }
 
+    pub fn rank_index(&self) -> usize {
+        self.to_index() / BOARD_WIDTH
+    }
+
     //pub fn rank(&self) -> Rank {
     //    let i = self.clone() as usize;
     //    Rank::from_index(i / BOARD_WIDTH)
@@ -613,6 +645,18 @@ mod tests {
         assert_eq!(Square::from_str("p16"), Square::P16);
     }
 
+    #[test]
+    fn short_str_works() {
+        assert_eq!(Square::from_short_str("a1"), Some(Square::A1));
+        assert_eq!(Square::from_short_str("j10"), Some(Square::J10));
+        assert_eq!(Square::from_short_str("p16"), Some(Square::P16));
+        assert_eq!(Square::from_short_str("a01"), None);
+        assert_eq!(Square::from_short_str("q1"), None);
+        assert_eq!(Square::from_short_str("a17"), None);
+        assert_eq!(Square::N14.to_short_str(), "n14");
+        assert_eq!(Square::N4.to_str(), "n04");
+    }
+
     #[test]
     fn square_file_works() {
         assert_eq!(Square::A1.file(), File::A);
@@ -0,0 +1,283 @@
+use chessops::{Move, Position, PositionError, San, SanAction, SanError};
+use serde::{Deserialize, Serialize};
+
+use crate::game_handler::{error_to_str, san_error_to_str};
+
+/// Why an analysis request failed.  `error` is meant for programs, e.g. `IllegalMove` or
+/// `InvalidFen`, and `message` for people.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct AnalysisError {
+    error: &'static str,
+    message: String,
+}
+
+impl AnalysisError {
+    pub fn internal() -> Self {
+        Self {
+            error: "Internal",
+            message: "Analysis failed".to_string(),
+        }
+    }
+
+    fn from_position_error(err: PositionError) -> Self {
+        let error = match err {
+            PositionError::IllegalMove => "IllegalMove",
+            PositionError::NotOwnColor => "NotOwnColor",
+            PositionError::FirstMoveNotWhite => "FirstMoveNotWhite",
+            PositionError::DefectMoveKing => "DefectMoveKing",
+        };
+        Self {
+            error,
+            message: error_to_str(err).to_string(),
+        }
+    }
+
+    fn from_san_error(err: SanError) -> Self {
+        let error = match err {
+            SanError::InvalidNotation => "InvalidNotation",
+            SanError::IllegalMove => "IllegalMove",
+            SanError::AmbiguousMove => "AmbiguousMove",
+        };
+        Self {
+            error,
+            message: san_error_to_str(err).to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveParams {
+    fen: String,
+    /// A move in long (`WNf01g03`) or short (`wNg3`) notation, a defection (`D:navy`), or
+    /// Player 2's choice after the first move (`accept` or `reject`)
+    san: String,
+    /// The last action was a defection that left the King on a square of its own color, so
+    /// the King has to move
+    #[serde(default)]
+    king_must_move: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MoveResult {
+    fen: String,
+    /// Short notation of the action, see `chessops::San`
+    notation: String,
+    check: bool,
+    checkmate: bool,
+    /// The defection left the King on a square of its own color
+    king_must_move: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LegalParams {
+    fen: String,
+    /// See `MoveParams`
+    #[serde(default)]
+    king_must_move: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LegalMove {
+    /// Long notation, e.g. `WNf01g03`
+    san: String,
+    /// Short notation, e.g. `wNg3`
+    notation: String,
+    /// The move itself, e.g. `{"color": "white", "role": "knight", "from": "f1", "to": "g3"}`
+    #[serde(rename = "move")]
+    move_: Move,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LegalActions {
+    moves: Vec<LegalMove>,
+    /// Armies the active player can defect to
+    defections: Vec<&'static str>,
+}
+
+fn parse_fen(fen: &str) -> Result<Position, AnalysisError> {
+    Position::try_from_fen(fen).map_err(|err| AnalysisError {
+        error: "InvalidFen",
+        message: format!("Invalid FEN: {}", err),
+    })
+}
+
+/// Play a single action on a custom position, following the same rules as `GameHandler`
+pub fn play(params: &MoveParams) -> Result<MoveResult, AnalysisError> {
+    let mut pos = parse_fen(&params.fen)?;
+    let mut king_must_move = false;
+
+    // Only Player 2 chooses, right after the first move
+    let is_choice = pos.p1_owned().is_none() && pos.active_player() == 2;
+    let notation = match params.san.as_str() {
+        "accept" if is_choice => {
+            pos.accept_first_move();
+            params.san.clone()
+        }
+        "reject" if is_choice => {
+            pos.reject_first_move();
+            params.san.clone()
+        }
+        _ if is_choice => {
+            return Err(AnalysisError {
+                error: "FirstMoveChoice",
+                message: "Player 2 must accept or reject the first move".to_string(),
+            });
+        }
+        san => {
+            // Long notation starts with the army in uppercase, e.g. `WNf01g03`
+            let is_long =
+                !san.starts_with("D:") && san.starts_with(|c: char| c.is_ascii_uppercase());
+            let action = if is_long {
+                Move::try_from_san(san)
+                    .map(SanAction::Move)
+                    .ok_or(SanError::InvalidNotation)
+            } else {
+                San::parse(&pos, san)
+            };
+
+            match action.map_err(AnalysisError::from_san_error)? {
+                SanAction::Move(chess_move) => {
+                    let pos_before = pos.clone();
+                    let result = if params.king_must_move {
+                        pos.play_move_after_defect(&chess_move)
+                    } else {
+                        pos.play_move(&chess_move)
+                    };
+                    result.map_err(AnalysisError::from_position_error)?;
+                    San::from_move(&pos_before, &chess_move)
+                }
+                SanAction::Defect(color) => {
+                    if params.king_must_move || pos.p1_owned().is_none() {
+                        return Err(AnalysisError::from_position_error(
+                            PositionError::IllegalMove,
+                        ));
+                    }
+                    match pos.defect_to(color) {
+                        Ok(()) => {}
+                        Err(PositionError::DefectMoveKing) => king_must_move = true,
+                        Err(err) => return Err(AnalysisError::from_position_error(err)),
+                    }
+                    San::from_defect(&color)
+                }
+            }
+        }
+    };
+
+    Ok(MoveResult {
+        fen: pos.to_fen(),
+        notation,
+        check: pos.is_check(),
+        checkmate: pos.is_checkmate(),
+        king_must_move,
+    })
+}
+
+/// Every move the active player can make on a custom position, and the armies they can
+/// defect to
+pub fn legal(params: &LegalParams) -> Result<LegalActions, AnalysisError> {
+    let pos = parse_fen(&params.fen)?;
+
+    let legal_moves = pos.legal_moves();
+    let moves = legal_moves
+        .iter()
+        .filter(|m| !params.king_must_move || m.role == chessops::Role::King)
+        .map(|m| LegalMove {
+            san: m.to_san(),
+            notation: San::from_legal_move(&pos, m, &legal_moves),
+            move_: m.clone(),
+        })
+        .collect();
+
+    let mut defections: Vec<&'static str> = if params.king_must_move {
+        Vec::new()
+    } else {
+        pos.controlled(pos.active_player())
+            .iter()
+            .map(|c| c.to_name())
+            .collect()
+    };
+    defections.sort_unstable();
+
+    Ok(LegalActions { moves, defections })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Player 1 owns White and controls Navy, with a Navy Rook on b9.  Player 2 owns Black.
+    const FEN: &str = "bk15/16/16/16/16/16/16/01nr14/16/16/16/16/16/16/16/wk15 1 w n b - 3";
+
+    fn params(fen: &str, san: &str) -> MoveParams {
+        MoveParams {
+            fen: fen.to_string(),
+            san: san.to_string(),
+            king_must_move: false,
+        }
+    }
+
+    #[test]
+    fn play_works() {
+        let result = play(&params(&Position::new_fen(), "wNg3")).unwrap();
+        assert_eq!(result.notation, "wNg3");
+        assert!(!result.check);
+
+        let result = play(&params(&result.fen, "accept")).unwrap();
+        assert_eq!(result.notation, "accept");
+
+        let result = play(&params(FEN, "NRb09b16")).unwrap();
+        assert_eq!(result.notation, "nRb16+");
+        assert!(result.check);
+    }
+
+    #[test]
+    fn play_rejects_invalid_actions() {
+        assert_eq!(
+            play(&params("16/16", "wNg3")).unwrap_err().error,
+            "InvalidFen"
+        );
+        assert_eq!(
+            play(&params(&Position::new_fen(), "bNg14"))
+                .unwrap_err()
+                .error,
+            "IllegalMove"
+        );
+        assert_eq!(
+            play(&params(&Position::new_fen(), "BNf16g14"))
+                .unwrap_err()
+                .error,
+            "FirstMoveNotWhite"
+        );
+        assert_eq!(
+            play(&params(FEN, "D:green")).unwrap_err().error,
+            "IllegalMove"
+        );
+    }
+
+    #[test]
+    fn legal_works() {
+        let actions = legal(&LegalParams {
+            fen: FEN.to_string(),
+            king_must_move: false,
+        })
+        .unwrap();
+        assert_eq!(actions.defections, vec!["navy"]);
+        let check = actions
+            .moves
+            .iter()
+            .find(|m| m.notation == "nRb16+")
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(check).unwrap()["move"],
+            serde_json::json!({"color": "navy", "role": "rook", "from": "b9", "to": "b16"})
+        );
+
+        let actions = legal(&LegalParams {
+            fen: FEN.to_string(),
+            king_must_move: true,
+        })
+        .unwrap();
+        assert!(actions.defections.is_empty());
+        assert!(actions.moves.iter().all(|m| m.san.starts_with("WK")));
+    }
+}
@@ -0,0 +1,675 @@
+//! Terminal client for playing games without the web front end.
+//!
+//! Usage: `cargo run --bin client -- [SERVER_URL] [USERNAME]`
+//!
+//! The server defaults to `http://localhost:3000`.  Without a username, a new anonymous user is
+//! created through `POST /api/users`.  Pass its name back in later to resume your games.
+
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::error::Error;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines, Stdin};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use chessops::{Color, Move, Position};
+
+const DEFAULT_SERVER: &str = "http://localhost:3000";
+
+const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+type Input = Lines<BufReader<Stdin>>;
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type ClientResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+#[derive(Debug, Deserialize)]
+struct User {
+    name: String,
+}
+
+/// The parts of `GamePage` we display in the lobby
+#[derive(Debug, Deserialize)]
+struct GamePage {
+    games: Vec<GameSummary>,
+    next_cursor: Option<String>,
+}
+
+/// The parts of `GameWithoutMoves` we display in the lobby
+#[derive(Debug, Deserialize)]
+struct GameSummary {
+    pid: String,
+    state: String,
+    active_player: u8,
+    to_move: Option<String>,
+}
+
+/// The parts of `Game` we need to follow a game
+#[derive(Debug, Deserialize)]
+struct GameView {
+    pid: String,
+    moves: Vec<MoveView>,
+    player1: Option<String>,
+    player2: Option<String>,
+    state: String,
+    #[serde(default)]
+    result: Option<String>,
+    #[serde(default)]
+    takeback_by: Option<u8>,
+    /// Only sent to Player 1
+    #[serde(default)]
+    invite_token: Option<String>,
+    /// Only ever our own
+    #[serde(default)]
+    premove: Option<PremoveView>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PremoveView {
+    player: u8,
+    san: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MoveView {
+    san: String,
+    #[serde(default)]
+    notation: String,
+    fen: String,
+}
+
+/// Every websocket message, see `websocket::serve_play_game`
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    seq: Option<u64>,
+    t: String,
+    #[serde(default)]
+    d: serde_json::Value,
+}
+
+/// `d` of a `move` event
+#[derive(Debug, Deserialize)]
+struct MoveUpdate {
+    ply: usize,
+    #[serde(rename = "move")]
+    game_move: MoveView,
+    state: String,
+    result: Option<String>,
+}
+
+/// `d` of a `join` event
+#[derive(Debug, Deserialize)]
+struct JoinUpdate {
+    player1: Option<String>,
+    player2: Option<String>,
+    state: String,
+}
+
+/// `d` of a `takeback` event
+#[derive(Debug, Deserialize)]
+struct TakebackUpdate {
+    ply: usize,
+    state: String,
+}
+
+/// `d` of a `takeback_request` event
+#[derive(Debug, Deserialize)]
+struct TakebackRequestUpdate {
+    by: Option<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorView {
+    message: String,
+}
+
+/// The parts of `ChatMessage` we display
+#[derive(Debug, Deserialize)]
+struct ChatView {
+    user: String,
+    text: String,
+}
+
+struct Client {
+    http: reqwest::Client,
+    server: String,
+    user: User,
+}
+
+/// What we know about the game being played, kept across reconnections
+struct Session {
+    game: Option<GameView>,
+    /// Latest event received
+    seq: Option<u64>,
+    /// From the invite link, to join a private game
+    token: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> ClientResult<()> {
+    let mut args = std::env::args().skip(1);
+    let server = args
+        .next()
+        .unwrap_or(DEFAULT_SERVER.to_string())
+        .trim_end_matches('/')
+        .to_string();
+    let http = reqwest::Client::new();
+
+    let user = match args.next() {
+        Some(name) => User { name },
+        None => {
+            let user: User = http
+                .post(format!("{}/api/users", server))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            println!("Created user {}", user.name);
+            user
+        }
+    };
+
+    let client = Client { http, server, user };
+    let mut input = BufReader::new(tokio::io::stdin()).lines();
+
+    print_lobby_help();
+    loop {
+        println!("lobby>");
+        let Some(line) = input.next_line().await? else {
+            return Ok(());
+        };
+        let mut words = line.split_whitespace();
+
+        let result = match (words.next(), words.next(), words.next()) {
+            (Some("list"), None, None) => client.list_games(false).await,
+            (Some("list"), Some("turn"), None) => client.list_games(true).await,
+            (Some("new"), _, _) => {
+                let options: Vec<&str> = line.split_whitespace().skip(1).collect();
+                match new_game_params(&options) {
+                    Some(params) => match client.create_game(params).await {
+                        Ok(pid) => client.play(&pid, None, &mut input).await,
+                        Err(err) => Err(err),
+                    },
+                    None => {
+                        print_lobby_help();
+                        Ok(())
+                    }
+                }
+            }
+            (Some("join"), Some(pid), token) => client.play(pid, token, &mut input).await,
+            (Some("cancel"), Some(pid), None) => client.cancel_game(pid).await,
+            (Some("quit"), None, None) => return Ok(()),
+            (None, _, _) => Ok(()),
+            _ => {
+                print_lobby_help();
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            println!("Error: {}", err);
+        }
+    }
+}
+
+impl Client {
+    /// The most recent page of games, only those waiting on our move if `my_turn`
+    async fn list_games(&self, my_turn: bool) -> ClientResult<()> {
+        let page: GamePage = self
+            .http
+            .get(format!("{}/api/games", self.server))
+            .query(&[("my_turn", my_turn)])
+            .bearer_auth(&self.user.name)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if page.games.is_empty() {
+            println!("No games yet.  Use `new` to create one.");
+        }
+        for game in &page.games {
+            let to_move = match &game.to_move {
+                Some(name) => format!("{} (Player {}) to move", name, game.active_player),
+                None => format!("Player {} to move", game.active_player),
+            };
+            println!("{}  {:<14} {}", game.pid, game.state, to_move);
+        }
+        if page.next_cursor.is_some() {
+            println!(
+                "Only the {} most recently updated games are shown",
+                page.games.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the new game's ID
+    async fn create_game(&self, params: serde_json::Value) -> ClientResult<String> {
+        let game: GameView = self
+            .http
+            .post(format!("{}/api/games", self.server))
+            .bearer_auth(&self.user.name)
+            .json(&params)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        match &game.invite_token {
+            Some(token) => println!(
+                "Created private game {}.  Your opponent joins with `join {} {}`.",
+                game.pid, game.pid, token
+            ),
+            None => println!(
+                "Created game {}.  Share the ID with your opponent.",
+                game.pid
+            ),
+        }
+        Ok(game.pid)
+    }
+
+    async fn cancel_game(&self, pid: &str) -> ClientResult<()> {
+        self.http
+            .post(format!("{}/api/games/{}/cancel", self.server, pid))
+            .bearer_auth(&self.user.name)
+            .send()
+            .await?
+            .error_for_status()?;
+        println!("Cancelled game {}", pid);
+        Ok(())
+    }
+
+    /// Follow game `pid` over the websocket until the user leaves, reconnecting if the
+    /// connection drops
+    async fn play(&self, pid: &str, token: Option<&str>, input: &mut Input) -> ClientResult<()> {
+        let mut session = Session {
+            game: None,
+            seq: None,
+            token: token.map(|t| t.to_string()),
+        };
+        let mut attempts = 0;
+
+        print_game_help();
+        loop {
+            let mut url = format!(
+                "{}/ws/v0/play/{}?user={}",
+                self.server.replacen("http", "ws", 1),
+                pid,
+                self.user.name,
+            );
+            // Only ask for the events we missed
+            if let Some(seq) = session.seq {
+                url.push_str(&format!("&since={}", seq));
+            }
+
+            match tokio_tungstenite::connect_async(url).await {
+                Ok((socket, _)) => {
+                    attempts = 0;
+                    match self.follow(socket, &mut session, input).await? {
+                        Exit::Leave => return Ok(()),
+                        Exit::Disconnected => println!("Connection lost"),
+                    }
+                }
+                Err(err) if attempts >= MAX_RECONNECT_ATTEMPTS || session.seq.is_none() => {
+                    return Err(err.into());
+                }
+                Err(_) => {}
+            }
+
+            attempts += 1;
+            println!("Reconnecting...");
+            tokio::time::sleep(RECONNECT_DELAY * attempts).await;
+        }
+    }
+
+    async fn follow(
+        &self,
+        mut socket: Socket,
+        session: &mut Session,
+        input: &mut Input,
+    ) -> ClientResult<Exit> {
+        loop {
+            tokio::select! {
+                message = socket.next() => {
+                    let text = match message {
+                        Some(Ok(Message::Text(text))) => text,
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                            return Ok(Exit::Disconnected);
+                        }
+                        Some(Ok(_)) => continue,
+                    };
+                    let Ok(envelope) = serde_json::from_str::<Envelope>(&text) else {
+                        continue;
+                    };
+                    if let Some(seq) = envelope.seq {
+                        session.seq = Some(seq);
+                    }
+
+                    match envelope.t.as_str() {
+                        "game" => {
+                            let game: GameView = serde_json::from_value(envelope.d)?;
+                            if game.state == "Created" && !self.is_player(&game) {
+                                let join = json!({"t": "join", "d": {"token": session.token}});
+                                let join = join.to_string();
+                                socket.send(Message::Text(join)).await?;
+                            }
+                            self.print_game(&game);
+                            session.game = Some(game);
+                        }
+                        "move" | "join" | "takeback" | "takeback_request" | "cancel" => {
+                            let Some(game) = &mut session.game else {
+                                continue;
+                            };
+                            if !apply_update(game, &envelope.t, envelope.d)? {
+                                // Reconnect without `since` to get the whole game again
+                                println!("Out of sync with the server");
+                                session.seq = None;
+                                return Ok(Exit::Disconnected);
+                            }
+                            self.print_game(game);
+                        }
+                        "premove" => {
+                            let Some(game) = &mut session.game else {
+                                continue;
+                            };
+                            game.premove = serde_json::from_value(envelope.d)?;
+                            match &game.premove {
+                                Some(premove) => println!("Premove queued: {}", premove.san),
+                                None => println!("Premove cancelled"),
+                            }
+                        }
+                        "error" => {
+                            let err: ErrorView = serde_json::from_value(envelope.d)?;
+                            println!("Server error: {}", err.message);
+                        }
+                        "chat" => {
+                            let message: ChatView = serde_json::from_value(envelope.d)?;
+                            println!("<{}> {}", message.user, message.text);
+                        }
+                        "chat_history" => {
+                            let messages: Vec<ChatView> = serde_json::from_value(envelope.d)?;
+                            for message in messages {
+                                println!("<{}> {}", message.user, message.text);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                line = input.next_line() => {
+                    let Some(line) = line? else {
+                        return Ok(Exit::Leave);
+                    };
+                    let Some(game) = &session.game else {
+                        println!("Waiting for the game to load...");
+                        continue;
+                    };
+
+                    match self.command(game, line.trim()) {
+                        Command::Send(message) => {
+                            socket.send(Message::Text(message.to_string())).await?;
+                        }
+                        Command::Leave => {
+                            let _ = socket.close(None).await;
+                            return Ok(Exit::Leave);
+                        }
+                        Command::None => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Turn a line typed by the user into a websocket message
+    fn command(&self, game: &GameView, line: &str) -> Command {
+        if let Some(text) = line.strip_prefix("say ") {
+            return Command::Send(json!({"t": "chat", "d": {"text": text}}));
+        }
+        let pos = latest_position(game);
+        let mut words = line.split_whitespace();
+
+        match (words.next(), words.next()) {
+            (None, _) => Command::None,
+            (Some("leave"), None) => Command::Leave,
+            (Some("board"), None) => {
+                self.print_game(game);
+                Command::None
+            }
+            (Some("moves"), None) => {
+                let moves: Vec<String> = pos.legal_moves().iter().map(|m| m.to_san()).collect();
+                println!("{}", moves.join(" "));
+                Command::None
+            }
+            (Some(choice @ ("accept" | "reject")), None) => {
+                Command::Send(json!({"t": "first_move_choice", "d": choice}))
+            }
+            (Some("premove"), Some("cancel")) => Command::Send(json!({"t": "premove", "d": null})),
+            (Some("premove"), Some(san)) if Move::try_from_san(san).is_some() => {
+                Command::Send(json!({"t": "premove", "d": {"san": san}}))
+            }
+            (Some("takeback"), None) => Command::Send(json!({"t": "takeback"})),
+            (Some("takeback"), Some(choice @ ("accept" | "decline"))) => {
+                Command::Send(json!({"t": "takeback_choice", "d": choice}))
+            }
+            (Some("defect"), Some(name)) => {
+                match Color::all().into_iter().find(|c| c.to_name() == name) {
+                    Some(color) => Command::Send(json!({"t": "defect", "d": color.to_name()})),
+                    None => {
+                        println!("Unknown army: {}", name);
+                        Command::None
+                    }
+                }
+            }
+            (Some(san), None) if Move::try_from_san(san).is_some() => {
+                let t = if game.state == "Accepted" {
+                    "first_move"
+                } else {
+                    "move"
+                };
+                Command::Send(json!({"t": t, "d": {"san": san}}))
+            }
+            _ => {
+                print_game_help();
+                Command::None
+            }
+        }
+    }
+
+    fn is_player(&self, game: &GameView) -> bool {
+        game.player1.as_ref() == Some(&self.user.name)
+            || game.player2.as_ref() == Some(&self.user.name)
+    }
+
+    fn print_game(&self, game: &GameView) {
+        let pos = latest_position(game);
+        println!();
+        println!("{}", pos);
+        println!(
+            "Game {} | {} vs {} | {}",
+            game.pid,
+            game.player1.as_deref().unwrap_or("?"),
+            game.player2.as_deref().unwrap_or("?"),
+            game.state,
+        );
+
+        if let Some(last) = game.moves.last().filter(|m| !m.san.is_empty()) {
+            let notation = if last.notation.is_empty() {
+                &last.san
+            } else {
+                &last.notation
+            };
+            println!("Last move: {}", notation);
+        }
+        if let Some(premove) = &game.premove {
+            println!("Premove: {}", premove.san);
+        }
+        if let Some(player) = game.takeback_by {
+            println!(
+                "Player {} asked for a takeback.  Their opponent can type `takeback accept` or `takeback decline`",
+                player
+            );
+        }
+
+        let prompt = match game.state.as_str() {
+            "Created" => "Waiting for an opponent to join",
+            "Cancelled" => "This game was cancelled",
+            "Ended" => match game.result.as_deref() {
+                Some("Player1Won") => "Player 1 won",
+                Some("Player2Won") => "Player 2 won",
+                Some("Draw") => "Draw",
+                _ => "Game over",
+            },
+            "FirstMove" if self.is_active(game, &pos) => "Type `accept` or `reject`",
+            "DefectMoveKing" if self.is_active(game, &pos) => "Move your King off its square",
+            _ if self.is_active(game, &pos) => "Your move",
+            _ => "Waiting for your opponent",
+        };
+        println!("{}", prompt);
+    }
+
+    fn is_active(&self, game: &GameView, pos: &Position) -> bool {
+        let active = match pos.active_player() {
+            1 => &game.player1,
+            _ => &game.player2,
+        };
+        active.as_ref() == Some(&self.user.name)
+    }
+}
+
+enum Exit {
+    Leave,
+    Disconnected,
+}
+
+enum Command {
+    Send(serde_json::Value),
+    Leave,
+    None,
+}
+
+/// Apply an event to our copy of the game.  Returns false if we missed moves.
+fn apply_update(game: &mut GameView, t: &str, d: serde_json::Value) -> ClientResult<bool> {
+    match t {
+        "join" => {
+            let update: JoinUpdate = serde_json::from_value(d)?;
+            game.player1 = update.player1;
+            game.player2 = update.player2;
+            game.state = update.state;
+            return Ok(true);
+        }
+        "cancel" => {
+            game.state = "Cancelled".to_string();
+            return Ok(true);
+        }
+        "takeback_request" => {
+            let update: TakebackRequestUpdate = serde_json::from_value(d)?;
+            game.takeback_by = update.by;
+            return Ok(true);
+        }
+        "takeback" => {
+            let update: TakebackUpdate = serde_json::from_value(d)?;
+            if update.ply >= game.moves.len() {
+                return Ok(false);
+            }
+            game.moves.truncate(update.ply + 1);
+            game.state = update.state;
+            game.takeback_by = None;
+            return Ok(true);
+        }
+        _ => {}
+    }
+
+    let update: MoveUpdate = serde_json::from_value(d)?;
+    // Events we already have can be replayed after reconnecting
+    if update.ply < game.moves.len() {
+        return Ok(true);
+    }
+    if update.ply > game.moves.len() {
+        return Ok(false);
+    }
+    game.moves.push(update.game_move);
+    game.state = update.state;
+    game.result = update.result;
+    game.takeback_by = None;
+    // Our premove has been played or dropped once it's our turn
+    let active = latest_position(game).active_player();
+    if game.premove.as_ref().is_some_and(|p| p.player == active) {
+        game.premove = None;
+    }
+    Ok(true)
+}
+
+fn latest_position(game: &GameView) -> Position {
+    let fen = game
+        .moves
+        .last()
+        .map(|m| m.fen.clone())
+        .unwrap_or(Position::new_fen());
+    Position::from_fen(fen)
+}
+
+/// Parse the options of `new`, e.g. `private second` or `vs anon1234567 random shuffled 42`
+fn new_game_params(options: &[&str]) -> Option<serde_json::Value> {
+    let mut params = json!({});
+    let mut options = options.iter().peekable();
+    while let Some(option) = options.next() {
+        match *option {
+            "private" => params["private"] = json!(true),
+            "vs" => params["opponent"] = json!(options.next()?),
+            "first" | "second" | "random" => params["order"] = json!(option),
+            "shuffled" => {
+                params["shuffled"] = json!(true);
+                if let Some(seed) = options.peek().and_then(|s| s.parse::<u32>().ok()) {
+                    params["seed"] = json!(seed);
+                    options.next();
+                }
+            }
+            "fen" => {
+                // The FEN's seven fields follow
+                let fields: Vec<&str> = options.by_ref().take(7).copied().collect();
+                if fields.len() != 7 {
+                    return None;
+                }
+                params["fen"] = json!(fields.join(" "));
+            }
+            _ => return None,
+        }
+    }
+    Some(params)
+}
+
+fn print_lobby_help() {
+    println!("Commands:");
+    println!("  list                 show your games");
+    println!("  list turn            show your games waiting on your move");
+    println!("  new                  create a game and start playing it");
+    println!("  new private          create a game only invited players can join");
+    println!("  new vs <user>        create a game only <user> can join");
+    println!("  new ... first|second|random");
+    println!("                       choose whether you move first, second or at random");
+    println!("  new ... fen <fen>    start from a custom position");
+    println!("  new ... shuffled [seed]");
+    println!("                       start from a shuffled position, the same for the same seed");
+    println!("  join <id> [token]    join or resume a game, with the token for private games");
+    println!("  cancel <id>          cancel a game nobody has joined yet");
+    println!("  quit");
+}
+
+fn print_game_help() {
+    println!("Commands:");
+    println!("  <move>           play a move in long notation, e.g. WNf01g03 or WPj15j16=Q");
+    println!("  accept, reject   choose whether to keep your opponent's first move");
+    println!("  defect <army>    defect to an army you control, e.g. defect navy");
+    println!("  premove <move>   queue a move to play as soon as it is your turn");
+    println!("  premove cancel");
+    println!("  takeback         ask to take back your last move");
+    println!("  takeback accept, takeback decline");
+    println!("                   answer your opponent's takeback request");
+    println!("  moves            list the legal moves in this position");
+    println!("  say <message>    chat with the other players, or the other spectators");
+    println!("  board            print the board again");
+    println!("  leave            go back to the lobby");
+}
@@ -0,0 +1,231 @@
+use bson::serde_helpers::chrono_datetime_as_bson_datetime;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::game::Game;
+
+/// Longest message, in characters
+pub const MAX_LENGTH: usize = 500;
+
+/// Messages sent to a client when it connects
+pub const HISTORY_SIZE: i64 = 100;
+
+/// Each user may send at most `RATE_LIMIT` messages per `RATE_WINDOW`, across all games
+const RATE_LIMIT: usize = 5;
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// On top of that, each connection may send at most `CONNECTION_RATE_LIMIT` messages per
+/// `CONNECTION_RATE_WINDOW`
+const CONNECTION_RATE_LIMIT: usize = 2;
+const CONNECTION_RATE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Players and spectators each talk among themselves
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatChannel {
+    Players,
+    Spectators,
+}
+
+impl ChatChannel {
+    /// The channel `username` reads and writes in `game`
+    pub fn for_user(game: &Game, username: &str) -> Self {
+        let is_player = [&game.player1, &game.player2]
+            .iter()
+            .any(|p| p.as_deref() == Some(username));
+        if is_player {
+            ChatChannel::Players
+        } else {
+            ChatChannel::Spectators
+        }
+    }
+}
+
+/// A chat message, stored in the `chat` collection
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChatMessage {
+    /// Game the message was sent in
+    pub pid: String,
+    pub channel: ChatChannel,
+    pub user: String,
+    pub text: String,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub ts: DateTime<Utc>,
+}
+
+impl ChatMessage {
+    /// Fails if `text` is blank or longer than `MAX_LENGTH` once trimmed
+    pub fn new(game: &Game, username: &str, text: &str) -> Result<Self, &'static str> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err("Message is empty");
+        }
+        if text.chars().count() > MAX_LENGTH {
+            return Err("Message is too long");
+        }
+        Ok(Self {
+            pid: game.pid.clone(),
+            channel: ChatChannel::for_user(game, username),
+            user: username.to_string(),
+            text: text.to_string(),
+            ts: Utc::now(),
+        })
+    }
+}
+
+/// Tracks when each user last sent messages, to limit how fast they can chat
+#[derive(Debug)]
+pub struct ChatLimiter {
+    sent: Mutex<HashMap<String, VecDeque<Instant>>>,
+    limit: usize,
+    window: Duration,
+}
+
+impl Default for ChatLimiter {
+    fn default() -> Self {
+        Self::new(RATE_LIMIT, RATE_WINDOW)
+    }
+}
+
+impl ChatLimiter {
+    fn new(limit: usize, window: Duration) -> Self {
+        Self {
+            sent: Mutex::default(),
+            limit,
+            window,
+        }
+    }
+
+    /// For a single connection, which only ever sends as one user
+    pub fn per_connection() -> Self {
+        Self::new(CONNECTION_RATE_LIMIT, CONNECTION_RATE_WINDOW)
+    }
+
+    /// Whether every one of `limiters` lets `username` send a message now.  If so, counts it in
+    /// each; a refused message counts in none.
+    pub fn allow_all(limiters: &[&ChatLimiter], username: &str) -> bool {
+        Self::allow_all_at(limiters, username, Instant::now())
+    }
+
+    fn allow_all_at(limiters: &[&ChatLimiter], username: &str, now: Instant) -> bool {
+        // Hold every lock until the message is counted, so that nothing else slips in between
+        let mut guards: Vec<_> = limiters.iter().map(|l| l.sent.lock().unwrap()).collect();
+        let allowed = limiters
+            .iter()
+            .zip(guards.iter_mut())
+            .all(|(limiter, sent)| limiter.recent(sent, username, now).len() < limiter.limit);
+        if allowed {
+            for sent in guards.iter_mut() {
+                sent.entry(username.to_string()).or_default().push_back(now);
+            }
+        }
+        allowed
+    }
+
+    /// The times `username` sent messages within the window, forgetting older ones
+    fn recent<'a>(
+        &self,
+        sent: &'a mut HashMap<String, VecDeque<Instant>>,
+        username: &str,
+        now: Instant,
+    ) -> &'a VecDeque<Instant> {
+        // Forget users who have been quiet for a while
+        sent.retain(|_, times| {
+            times
+                .back()
+                .is_some_and(|t| now.duration_since(*t) < self.window)
+        });
+
+        let times = sent.entry(username.to_string()).or_default();
+        while times
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= self.window)
+        {
+            times.pop_front();
+        }
+        times
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_works() {
+        let mut game = Game::new();
+        game.player1 = Some("anon1".to_string());
+
+        let message = ChatMessage::new(&game, "anon1", "  good luck ").unwrap();
+        assert_eq!(message.text, "good luck");
+        assert_eq!(message.channel, ChatChannel::Players);
+
+        let message = ChatMessage::new(&game, "anon2", "hi").unwrap();
+        assert_eq!(message.channel, ChatChannel::Spectators);
+
+        assert!(ChatMessage::new(&game, "anon1", " \n").is_err());
+        assert!(ChatMessage::new(&game, "anon1", &"x".repeat(MAX_LENGTH)).is_ok());
+        assert!(ChatMessage::new(&game, "anon1", &"x".repeat(MAX_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn limiter_works() {
+        let limiter = ChatLimiter::default();
+        let allow = |username, now| ChatLimiter::allow_all_at(&[&limiter], username, now);
+        let start = Instant::now();
+        for _ in 0..RATE_LIMIT {
+            assert!(allow("anon1", start));
+        }
+        assert!(!allow("anon1", start));
+        // Others are not affected
+        assert!(allow("anon2", start));
+
+        assert!(allow("anon1", start + RATE_WINDOW));
+    }
+
+    #[test]
+    fn refused_messages_count_in_no_limiter() {
+        let user = ChatLimiter::default();
+        let connection = ChatLimiter::per_connection();
+        let start = Instant::now();
+
+        // Other connections used up most of the user's quota
+        for _ in 0..RATE_LIMIT - 1 {
+            assert!(ChatLimiter::allow_all_at(&[&user], "anon1", start));
+        }
+        assert!(ChatLimiter::allow_all_at(
+            &[&connection, &user],
+            "anon1",
+            start
+        ));
+        // Refused by the user's limit, so it does not count for the connection
+        assert!(!ChatLimiter::allow_all_at(
+            &[&connection, &user],
+            "anon1",
+            start
+        ));
+        assert_eq!(connection.sent.lock().unwrap()["anon1"].len(), 1);
+
+        // Refused by the connection's limit, so it does not count for the user
+        let later = start + RATE_WINDOW;
+        for _ in 0..CONNECTION_RATE_LIMIT {
+            assert!(ChatLimiter::allow_all_at(
+                &[&connection, &user],
+                "anon1",
+                later
+            ));
+        }
+        assert!(!ChatLimiter::allow_all_at(
+            &[&connection, &user],
+            "anon1",
+            later
+        ));
+        assert_eq!(
+            user.sent.lock().unwrap()["anon1"].len(),
+            CONNECTION_RATE_LIMIT
+        );
+    }
+}
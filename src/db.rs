@@ -1,7 +1,15 @@
 use chrono::Utc;
-use mongodb::{bson::doc, Database};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Bson, Document},
+    options::{FindOptions, IndexOptions},
+    Database, IndexModel,
+};
 
-use crate::game::Game;
+use crate::chat::{self, ChatChannel, ChatMessage};
+use crate::explorer;
+use crate::game::{Game, GameState};
+use crate::notify::Webhook;
 use crate::user::User;
 
 pub async fn get_game(db: &Database, game_id: &str) -> Option<Game> {
@@ -15,9 +23,114 @@ pub async fn get_game(db: &Database, game_id: &str) -> Option<Game> {
             None
         }
     };
+
+    // Catch inconsistent positions before they cause a panic
+    #[cfg(debug_assertions)]
+    if let Some(game) = &game_option {
+        for (index, problem) in game.position_problems() {
+            tracing::error!("Game {} moves[{}]: {:?}", game.pid, index, problem);
+        }
+    }
+
     return game_option;
 }
 
+/// Indexes behind the game listing and search, see `game_list`, the explorer and the chat.
+/// Creating an index that already exists does nothing.
+pub async fn create_indexes(db: &Database) {
+    let games_coll = db.collection::<Game>("games");
+    let indexes = [
+        doc! { "pid": 1 },
+        doc! { "state": 1, "updated": -1, "pid": -1 },
+        doc! { "player1": 1, "updated": -1, "pid": -1 },
+        doc! { "player2": 1, "updated": -1, "pid": -1 },
+        doc! { "invited_player": 1, "updated": -1, "pid": -1 },
+        doc! { "waiting_on": 1, "updated": -1, "pid": -1 },
+    ]
+    .into_iter()
+    .map(|keys| IndexModel::builder().keys(keys).build());
+    if let Err(err) = games_coll.create_indexes(indexes, None).await {
+        tracing::error!("{:?}", err);
+    }
+
+    // One entry per move played from a position, see `explorer::add_game`
+    let explorer_coll = db.collection::<Document>("explorer");
+    let index = IndexModel::builder()
+        .keys(doc! { "key": 1, "san": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    if let Err(err) = explorer_coll.create_index(index, None).await {
+        tracing::error!("{:?}", err);
+    }
+
+    let chat_coll = db.collection::<ChatMessage>("chat");
+    let index = IndexModel::builder()
+        .keys(doc! { "pid": 1, "channel": 1, "ts": -1 })
+        .build();
+    if let Err(err) = chat_coll.create_index(index, None).await {
+        tracing::error!("{:?}", err);
+    }
+}
+
+pub async fn save_chat_message(db: &Database, message: &ChatMessage) -> bool {
+    let chat_coll = db.collection::<ChatMessage>("chat");
+    match chat_coll.insert_one(message, None).await {
+        Ok(_) => true,
+        Err(err) => {
+            tracing::error!("{:?}", err);
+            false
+        }
+    }
+}
+
+/// The latest `chat::HISTORY_SIZE` messages of a game's channel, oldest first
+pub async fn get_chat_messages(
+    db: &Database,
+    game_id: &str,
+    channel: ChatChannel,
+) -> Vec<ChatMessage> {
+    let chat_coll = db.collection::<ChatMessage>("chat");
+    let filter = doc! {
+        "pid": game_id,
+        "channel": bson::to_bson(&channel).unwrap(),
+    };
+    let options = FindOptions::builder()
+        .sort(doc! { "ts": -1 })
+        .limit(chat::HISTORY_SIZE)
+        .build();
+    let result = match chat_coll.find(filter, options).await {
+        Ok(cursor) => cursor.try_collect::<Vec<ChatMessage>>().await,
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(mut messages) => {
+            messages.reverse();
+            messages
+        }
+        Err(err) => {
+            tracing::error!("{:?}", err);
+            Vec::new()
+        }
+    }
+}
+
+/// Up to `limit` games matching `filter`, most recently updated first.  Only the last move of
+/// each game is loaded.
+pub async fn find_games(
+    db: &Database,
+    filter: Document,
+    limit: i64,
+) -> Result<Vec<Game>, mongodb::error::Error> {
+    let games_coll = db.collection::<Game>("games");
+    let options = FindOptions::builder()
+        .sort(doc! { "updated": -1, "pid": -1 })
+        .limit(limit)
+        .projection(doc! { "moves": { "$slice": -1 } })
+        .build();
+    games_coll.find(filter, options).await?.try_collect().await
+}
+
+/// Save the latest move.  If it ended the game, also add the game to the explorer.
 pub async fn save_game_move(db: &Database, game: &Game) {
     let games_coll = db.collection::<Game>("games");
     let filter = doc! { "pid": game.pid.clone() };
@@ -27,12 +140,96 @@ pub async fn save_game_move(db: &Database, game: &Game) {
         // Some moves alter the game state
         "$set": {
             "state": bson::to_bson(&game.state).unwrap(),
+            "result": bson::to_bson(&game.result).unwrap(),
+            "takeback_by": bson::to_bson(&game.takeback_by).unwrap(),
+            "premove": bson::to_bson(&game.premove).unwrap(),
+            "waiting_on": game.armies().to_move,
+            "updated": Utc::now(),
         },
         "$push": {
             "moves": bson::to_bson(latest_move).unwrap(),
         },
     };
     let _ = games_coll.update_one(filter, update, None).await;
+
+    if explorer::is_explorable(game) {
+        explorer::add_game(db, game).await;
+    }
+}
+
+pub async fn save_premove(db: &Database, game: &Game) {
+    let games_coll = db.collection::<Game>("games");
+    let filter = doc! { "pid": game.pid.clone() };
+    let update = doc! {
+        "$set": {
+            "premove": bson::to_bson(&game.premove).unwrap(),
+        },
+    };
+    let _ = games_coll.update_one(filter, update, None).await;
+}
+
+pub async fn save_takeback_request(db: &Database, game: &Game) {
+    let games_coll = db.collection::<Game>("games");
+    let filter = doc! { "pid": game.pid.clone() };
+    let update = doc! {
+        "$set": {
+            "takeback_by": bson::to_bson(&game.takeback_by).unwrap(),
+        },
+    };
+    let _ = games_coll.update_one(filter, update, None).await;
+}
+
+/// Truncate the stored moves to match `game`, which had `prev_len` moves before the takeback.
+/// Returns false if the stored game changed in the meantime.
+pub async fn save_takeback(db: &Database, game: &Game, prev_len: usize) -> bool {
+    let games_coll = db.collection::<Game>("games");
+    // Only matches if no move was added since we read the game
+    let filter = doc! {
+        "pid": game.pid.clone(),
+        "moves": { "$size": prev_len as i64 },
+    };
+    let update = doc! {
+        "$set": {
+            "state": bson::to_bson(&game.state).unwrap(),
+            "takeback_by": bson::to_bson(&game.takeback_by).unwrap(),
+            "premove": bson::to_bson(&game.premove).unwrap(),
+            "waiting_on": game.armies().to_move,
+            "updated": Utc::now(),
+        },
+        "$push": {
+            "moves": { "$each": [], "$slice": game.moves.len() as i64 },
+        },
+    };
+    match games_coll.update_one(filter, update, None).await {
+        Ok(result) => result.modified_count == 1,
+        Err(err) => {
+            tracing::error!("{:?}", err);
+            false
+        }
+    }
+}
+
+/// Returns false unless the game was still waiting for an opponent
+pub async fn cancel_game(db: &Database, game: &Game) -> bool {
+    let games_coll = db.collection::<Game>("games");
+    let filter = doc! {
+        "pid": game.pid.clone(),
+        "state": bson::to_bson(&GameState::Created).unwrap(),
+    };
+    let update = doc! {
+        "$set": {
+            "state": bson::to_bson(&GameState::Cancelled).unwrap(),
+            "waiting_on": None::<String>,
+            "updated": Utc::now(),
+        },
+    };
+    match games_coll.update_one(filter, update, None).await {
+        Ok(result) => result.modified_count == 1,
+        Err(err) => {
+            tracing::error!("{:?}", err);
+            false
+        }
+    }
 }
 
 pub async fn get_user(db: &Database, username: &str) -> Option<User> {
@@ -51,23 +248,58 @@ pub async fn get_user(db: &Database, username: &str) -> Option<User> {
     };
 }
 
-pub async fn update_player(db: &Database, game: &Game, user_id: &String) {
+/// Replace the user's webhook, or remove it if `webhook` is `None`
+pub async fn save_webhook(
+    db: &Database,
+    username: &str,
+    webhook: Option<&Webhook>,
+) -> Result<(), mongodb::error::Error> {
+    let user_coll = db.collection::<User>("users");
+    let filter = doc! { "name": username };
+    let update = doc! {
+        "$set": {
+            "webhook": bson::to_bson(&webhook).unwrap(),
+        },
+    };
+    user_coll.update_one(filter, update, None).await.map(|_| ())
+}
+
+/// Save the player who joined `game`, in whichever slot `Game::set_player_joined` used.
+/// Returns whether they got the slot, which fails if somebody else joined or the game was
+/// cancelled in the meantime.
+pub async fn update_player(db: &Database, game: &Game, user_id: &String) -> bool {
     // Validate user
-    // NOTE: We may need to make this an atomic transaction
-    if let Some(_) = get_user(&db, user_id.as_str()).await {
-        // Update game
-        let games_coll = db.collection::<Game>("games");
-        let filter = doc! { "pid": game.pid.clone() };
-
-        let update = doc! {
-            "$set": {
-                "player2": user_id,
-                "updated": Utc::now(),
-                "state": bson::to_bson(&game.state).unwrap(),
-            },
-        };
-        let _ = games_coll.update_one(filter, update, None).await;
-    } else {
+    if get_user(db, user_id.as_str()).await.is_none() {
         tracing::error!("User does not exist: {}", user_id);
+        return false;
+    }
+
+    let games_coll = db.collection::<Game>("games");
+    let slot = if game.player2.as_ref() == Some(user_id) {
+        "player2"
+    } else {
+        "player1"
+    };
+    // Only while the slot is still free
+    let filter = doc! {
+        "pid": game.pid.clone(),
+        "state": bson::to_bson(&GameState::Created).unwrap(),
+        slot: Bson::Null,
+    };
+    let update = doc! {
+        "$set": {
+            "player1": game.player1.clone(),
+            "player2": game.player2.clone(),
+            "updated": Utc::now(),
+            "state": bson::to_bson(&game.state).unwrap(),
+            "waiting_on": game.armies().to_move,
+        },
+    };
+    match games_coll.update_one(filter, update, None).await {
+        Ok(result) => result.modified_count == 1,
+        Err(err) => {
+            tracing::error!("{:?}", err);
+            false
+        }
     }
 }
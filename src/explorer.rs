@@ -0,0 +1,232 @@
+use chessops::Position;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::doc,
+    options::{FindOptions, UpdateOptions},
+    Database,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::game::{Game, GameResult, GameState};
+
+/**
+ * One move played from one position, across all finished games, see `is_explorable`
+ *
+ * Positions are keyed by the FEN without the ply, i.e. the board along with who owns and
+ * controls which army, so that the same position reached after a different number of moves
+ * counts as one.  Stored in the `explorer` collection along with that `key`.
+ */
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExplorerMove {
+    /// As stored in `Game.moves`, e.g. `WNf01g03` or `action:accept`
+    pub san: String,
+    /// Short notation, e.g. `wNg3`
+    pub notation: String,
+    /// Number of games the move was played in
+    pub games: i64,
+    pub player1_won: i64,
+    pub player2_won: i64,
+    pub draws: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExplorerParams {
+    pub fen: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExplorerResult {
+    /// Sum of `games` over all moves, i.e. how often the position was played on from
+    games: i64,
+    /// Most played first
+    moves: Vec<ExplorerMove>,
+}
+
+/// The FEN without the ply, in the canonical form `Position::to_fen` gives.  `None` for FENs
+/// that cannot be parsed.  Positions are not validated, since ones reached in play may not pass,
+/// see `Position::try_from_fen_unvalidated`.
+pub fn position_key(fen: &str) -> Option<String> {
+    let fen = Position::try_from_fen_unvalidated(fen).ok()?.to_fen();
+    fen.rsplit_once(' ').map(|(key, _ply)| key.to_string())
+}
+
+/// The position key, `san` and notation of each entry in `game.moves`, counting a move played
+/// from the same position twice in one game only once
+fn game_moves(game: &Game) -> Vec<(String, String, String)> {
+    let mut seen = HashSet::new();
+    game.moves
+        .windows(2)
+        .filter_map(|pair| {
+            let Some(key) = position_key(&pair[0].fen) else {
+                tracing::warn!(
+                    "Leaving out invalid position {} of {}",
+                    pair[0].fen,
+                    game.pid
+                );
+                return None;
+            };
+            let notation = if pair[1].notation.is_empty() {
+                pair[1].san.clone()
+            } else {
+                pair[1].notation.clone()
+            };
+            Some((key, pair[1].san.clone(), notation))
+        })
+        .filter(|(key, san, _)| seen.insert((key.clone(), san.clone())))
+        .collect()
+}
+
+/// Whether `game` belongs in the explorer: a finished public game that was played here, as
+/// opposed to imported
+pub fn is_explorable(game: &Game) -> bool {
+    game.result.is_some() && game.invite_token.is_none() && !game.imported
+}
+
+/// Add a finished game to the explorer, unless it is not `is_explorable`.  Does nothing if it
+/// was added before, so it is safe to call more than once.
+pub async fn add_game(db: &Database, game: &Game) {
+    let Some(result) = game.result.as_ref().filter(|_| is_explorable(game)) else {
+        return;
+    };
+    let games_coll = db.collection::<Game>("games");
+    let explorer_coll = db.collection::<ExplorerMove>("explorer");
+
+    // Claim the game first, so that concurrent calls cannot count it twice
+    let filter = doc! { "pid": game.pid.clone(), "explored": { "$ne": true } };
+    let update = doc! { "$set": { "explored": true } };
+    match games_coll.update_one(filter, update, None).await {
+        Ok(claimed) if claimed.modified_count == 1 => {}
+        Ok(_) => return,
+        Err(err) => {
+            tracing::error!("{:?}", err);
+            return;
+        }
+    }
+
+    let result_field = match result {
+        GameResult::Player1Won => "player1_won",
+        GameResult::Player2Won => "player2_won",
+        GameResult::Draw => "draws",
+    };
+    let options = UpdateOptions::builder().upsert(true).build();
+    for (key, san, notation) in game_moves(game) {
+        let filter = doc! { "key": key, "san": san };
+        let update = doc! {
+            "$inc": { "games": 1, result_field: 1 },
+            "$setOnInsert": { "notation": notation },
+        };
+        if let Err(err) = explorer_coll
+            .update_one(filter, update, options.clone())
+            .await
+        {
+            tracing::error!("{:?}", err);
+        }
+    }
+}
+
+/// Add finished games that are not in the explorer yet, e.g. ones that ended before it existed
+pub async fn add_pending_games(db: &Database) {
+    let games_coll = db.collection::<Game>("games");
+    // The same games as `is_explorable`
+    let filter = doc! {
+        "state": bson::to_bson(&GameState::Ended).unwrap(),
+        "explored": { "$ne": true },
+        "invite_token": bson::Bson::Null,
+        "imported": { "$ne": true },
+    };
+    let mut cursor = match games_coll.find(filter, None).await {
+        Ok(cursor) => cursor,
+        Err(err) => {
+            tracing::error!("{:?}", err);
+            return;
+        }
+    };
+    loop {
+        match cursor.try_next().await {
+            Ok(Some(game)) => add_game(db, &game).await,
+            Ok(None) => break,
+            Err(err) => {
+                tracing::error!("{:?}", err);
+                break;
+            }
+        }
+    }
+}
+
+/// The moves played from the position with `key`, see `position_key`
+pub async fn lookup(db: &Database, key: &str) -> Result<ExplorerResult, mongodb::error::Error> {
+    let explorer_coll = db.collection::<ExplorerMove>("explorer");
+    let options = FindOptions::builder()
+        .sort(doc! { "games": -1, "san": 1 })
+        .build();
+    let moves: Vec<ExplorerMove> = explorer_coll
+        .find(doc! { "key": key }, options)
+        .await?
+        .try_collect()
+        .await?;
+
+    Ok(ExplorerResult {
+        games: moves.iter().map(|m| m.games).sum(),
+        moves,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_key_works() {
+        let fen = Position::new_fen();
+        let key = position_key(&fen).unwrap();
+        assert!(fen.starts_with(&key));
+        assert_eq!(key.split(' ').count(), 6);
+
+        // Only the ply differs
+        let later = format!("{} 42", key);
+        assert_eq!(position_key(&later), Some(key));
+
+        assert_eq!(position_key("16/16 1 w - b - 0"), None);
+        // Black's King was captured, which `validate` does not allow
+        let no_black_king = "16/16/16/16/16/16/16/16/16/16/16/16/16/16/16/wk15 1 w - b - 2";
+        assert!(Position::try_from_fen(no_black_king).is_err());
+        assert!(position_key(no_black_king).is_some());
+    }
+
+    #[test]
+    fn game_moves_works() {
+        let mut game = Game::new();
+        let start = game.moves[0].fen.clone();
+        let mut pos = Position::new();
+        pos.play_move(&chessops::Move::from_san("WNf01g03"))
+            .unwrap();
+        game.add_move(pos.to_fen(), "WNf01g03".to_string(), "wNg3".to_string());
+        // Back to the start, as if the move had been taken back, then the same move again
+        game.add_move(start, "action:reject".to_string(), String::new());
+        game.add_move(pos.to_fen(), "WNf01g03".to_string(), "wNg3".to_string());
+
+        let moves = game_moves(&game);
+        assert_eq!(moves.len(), 2);
+        assert_eq!(moves[0].1, "WNf01g03");
+        assert_eq!(moves[0].2, "wNg3");
+        // Falls back to `san` without a notation
+        assert_eq!(moves[1].2, "action:reject");
+    }
+
+    #[test]
+    fn is_explorable_works() {
+        let mut game = Game::new();
+        assert!(!is_explorable(&game));
+        game.set_ended(GameResult::Draw);
+        assert!(is_explorable(&game));
+
+        let mut private = game.clone();
+        private.invite_token = Some("secret".to_string());
+        assert!(!is_explorable(&private));
+
+        let mut imported = game.clone();
+        imported.imported = true;
+        assert!(!is_explorable(&imported));
+    }
+}
@@ -1,10 +1,10 @@
 use bson::serde_helpers::chrono_datetime_as_bson_datetime;
+use chessops::{Color, Position, PositionProblem, Shuffle};
 use chrono::{DateTime, Utc};
 use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
 use std::default::Default;
 
-use crate::chessops::Position;
 use crate::user::User;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -15,6 +15,24 @@ pub enum GameState {
     InProgress,
     DefectMoveKing,
     Ended,
+    /// The creator withdrew the game before anyone joined
+    Cancelled,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GameResult {
+    Player1Won,
+    Player2Won,
+    Draw,
+}
+
+impl GameResult {
+    pub fn win_for(player: u8) -> Self {
+        match player {
+            1 => GameResult::Player1Won,
+            _ => GameResult::Player2Won,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -35,6 +53,70 @@ pub struct Game {
     pub player2: Option<String>,
 
     pub state: GameState,
+
+    /// Only set once the game has ended
+    #[serde(default)]
+    pub result: Option<GameResult>,
+
+    /// Rated games do not allow takebacks
+    #[serde(default)]
+    pub rated: bool,
+
+    /// Player (1 or 2) waiting for their opponent to accept a takeback
+    #[serde(default)]
+    pub takeback_by: Option<u8>,
+
+    /// Secret that must be presented to join a private game.  Only visible to the players, see
+    /// `redacted_for`.
+    #[serde(default)]
+    pub invite_token: Option<String>,
+
+    /// The only user allowed to join, if set
+    #[serde(default)]
+    pub invited_player: Option<String>,
+
+    /// Seed of the shuffled starting position, see `chessops::Shuffle`
+    #[serde(default)]
+    pub seed: Option<u32>,
+
+    /// Move queued by the player waiting for their turn.  Only visible to that player, see
+    /// `redacted_for`.
+    #[serde(default)]
+    pub premove: Option<Premove>,
+
+    /// Username of the player to move, see `Armies::to_move`.  Kept up to date by the `db`
+    /// functions that save moves, so that games can be filtered by whose turn it is.
+    #[serde(default)]
+    pub waiting_on: Option<String>,
+
+    /// Whether the finished game was added to the opening explorer, see `explorer::add_game`
+    #[serde(default)]
+    pub explored: bool,
+
+    /// Created from a record by `record::import` rather than played here.  Kept out of search
+    /// and the opening explorer, since nothing checks the players actually played it.
+    #[serde(default)]
+    pub imported: bool,
+}
+
+/// When the creator of a game wants to play
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayOrder {
+    /// As Player 1, making the first move with the white army
+    #[default]
+    First,
+    /// As Player 2, deciding whether to keep Player 1's first move
+    Second,
+    Random,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Premove {
+    /// Player (1 or 2) who queued the move
+    pub player: u8,
+    /// In the form `Move::from_san` understands
+    pub san: String,
 }
 
 impl Game {
@@ -55,20 +137,211 @@ impl Game {
             player1: None,
             player2: None,
             state: GameState::Created,
+            result: None,
+            rated: false,
+            takeback_by: None,
+            invite_token: None,
+            invited_player: None,
+            seed: None,
+            premove: None,
+            waiting_on: None,
+            explored: false,
+            imported: false,
+        }
+    }
+
+    /// A game starting from a custom position, e.g. for study, handicap or puzzles.  Without
+    /// owned armies, the game starts with Player 1's first move as usual.  Otherwise the
+    /// first-move choice is skipped and play starts as soon as the opponent joins.
+    pub fn from_fen(fen: &str) -> Result<Self, &'static str> {
+        let pos = Position::try_from_fen(fen).map_err(|_| "Invalid starting position")?;
+        if pos.p1_owned().is_none() && pos.active_player() != 1 {
+            return Err("Player 1 makes the first move");
+        }
+
+        let mut game = Game::new();
+        game.moves[0].fen = pos.to_fen();
+        Ok(game)
+    }
+
+    /// A game starting from the shuffled position generated from `seed`
+    pub fn shuffled(seed: u32) -> Self {
+        let mut game = Game::new();
+        game.moves[0].fen = Shuffle::fen(seed);
+        game.seed = Some(seed);
+        game
+    }
+
+    /// Position the game started from, the standard one unless created with `from_fen`
+    /// or `shuffled`
+    pub fn start_fen(&self) -> &str {
+        &self.moves[0].fen
+    }
+
+    /// State once both players have joined: `Accepted` if Player 1 still has to make the first
+    /// move, `InProgress` for custom positions where armies are already owned
+    fn start_state(&self) -> GameState {
+        if Position::from_fen(self.start_fen().to_string())
+            .p1_owned()
+            .is_some()
+        {
+            GameState::InProgress
+        } else {
+            GameState::Accepted
         }
     }
 
-    pub fn add_move(&mut self, fen: String, san: String) {
+    pub fn add_move(&mut self, fen: String, san: String, notation: String) {
         self.moves.push(Move {
             san: san,
             fen: fen,
+            notation,
             ..Default::default()
-        })
+        });
+        // A new move answers any pending takeback request
+        self.takeback_by = None;
+    }
+
+    /// The player who made `moves[index]`, i.e. the active player before it.  `index` must be
+    /// at least 1.
+    pub fn mover(&self, index: usize) -> u8 {
+        Position::from_fen(self.moves[index - 1].fen.clone()).active_player()
+    }
+
+    /// How many entries to remove from `moves` to undo `player`'s last move, along with their
+    /// opponent's reply if there was one.  `None` if `player` has no move to take back.
+    pub fn takeback_count(&self, player: u8) -> Option<usize> {
+        let last = self.moves.len() - 1;
+        (last.saturating_sub(1)..=last)
+            .rev()
+            .filter(|&i| i > 0)
+            .find(|&i| self.mover(i) == player)
+            .map(|i| self.moves.len() - i)
+    }
+
+    /// Remove the last `count` entries from `moves`, restoring the state of the game before them
+    pub fn take_back(&mut self, count: usize) {
+        self.moves.truncate(self.moves.len() - count);
+        self.takeback_by = None;
+        self.premove = None;
+
+        let last = self.moves.last().unwrap();
+        let is_first_move = Position::from_fen(last.fen.clone()).p1_owned().is_none();
+        self.state = match self.moves.len() {
+            1 => self.start_state(),
+            2 if is_first_move => GameState::FirstMove,
+            _ if last.san.starts_with("action:defect:") && last.san.ends_with('*') => {
+                GameState::DefectMoveKing
+            }
+            _ => GameState::InProgress,
+        };
+    }
+
+    /// Problems with any of the game's positions, along with their index in `moves`
+    pub fn position_problems(&self) -> Vec<(usize, PositionProblem)> {
+        self.moves
+            .iter()
+            .enumerate()
+            .flat_map(|(index, entry)| {
+                let problems = Position::from_fen(entry.fen.clone()).validate();
+                problems.into_iter().map(move |problem| (index, problem))
+            })
+            .collect()
+    }
+
+    pub fn set_ended(&mut self, result: GameResult) {
+        self.state = GameState::Ended;
+        self.result = Some(result);
+    }
+
+    /// Remove what `username` is not allowed to see, e.g. their opponent's premove
+    pub fn redacted_for(mut self, username: Option<&str>) -> Self {
+        let viewer = match username {
+            Some(name) if self.player1.as_deref() == Some(name) => Some(1),
+            Some(name) if self.player2.as_deref() == Some(name) => Some(2),
+            _ => None,
+        };
+        if self.premove.as_ref().map(|p| p.player) != viewer {
+            self.premove = None;
+        }
+        // The creator shares the invite link, and whoever joined already used it
+        if viewer.is_none() {
+            self.invite_token = None;
+        }
+        self
+    }
+
+    /// Make the game private.  Player 1 shares the token in an invite link.
+    pub fn set_invite_token(&mut self) {
+        self.invite_token = Some(nanoid!(21));
+    }
+
+    /// Whether `user` may join with `token`, the one from their invite link if any
+    pub fn can_join(&self, user: &User, token: Option<&str>) -> Result<(), &'static str> {
+        if self.player1.as_ref() == Some(&user.name) || self.player2.as_ref() == Some(&user.name) {
+            return Err("You cannot join your own game");
+        }
+        if self
+            .invited_player
+            .as_ref()
+            .is_some_and(|name| *name != user.name)
+        {
+            return Err("This game is for another player");
+        }
+        if self.invite_token.is_some() && self.invite_token.as_deref() != token {
+            return Err("This game is private, you need an invite link to join");
+        }
+        Ok(())
     }
 
+    /// Seat the user creating the game.  Player 1 makes the first move.
+    pub fn set_creator(&mut self, user: &User, order: PlayOrder) {
+        let plays_first = match order {
+            PlayOrder::First => true,
+            PlayOrder::Second => false,
+            PlayOrder::Random => rand::random(),
+        };
+        if plays_first {
+            self.player1 = Some(user.name.clone());
+        } else {
+            self.player2 = Some(user.name.clone());
+        }
+    }
+
+    /// Seat the joining user in whichever slot the creator left empty
     pub fn set_player_joined(&mut self, user: &User) {
-        self.state = GameState::Accepted;
-        self.player2 = Some(user.name.clone());
+        self.state = self.start_state();
+        if self.player1.is_none() {
+            self.player1 = Some(user.name.clone());
+        } else {
+            self.player2 = Some(user.name.clone());
+        }
+    }
+
+    /// Who owns and controls which army in the latest position
+    pub fn armies(&self) -> Armies {
+        let pos = Position::from_fen(self.moves.last().unwrap().fen.clone());
+        let active_player = pos.active_player();
+        let player_armies = |player: u8| {
+            let mut controlled: Vec<Color> = pos.controlled(player).iter().copied().collect();
+            controlled.sort_unstable_by_key(|c| c.to_name());
+            PlayerArmies {
+                owned: pos.owned(player),
+                controlled,
+            }
+        };
+        let to_move = match self.state {
+            GameState::Created | GameState::Ended | GameState::Cancelled => None,
+            _ if active_player == 1 => self.player1.clone(),
+            _ => self.player2.clone(),
+        };
+
+        Armies {
+            active_player,
+            to_move,
+            player1_armies: player_armies(1),
+            player2_armies: player_armies(2),
+        }
     }
 
     pub fn is_users_turn(&self, active_player: u8, user: &User) -> bool {
@@ -88,6 +361,11 @@ pub struct Move {
     /// Standard Algebraic Notation - notates the piece moved
     pub san: String,
 
+    /// Short algebraic notation meant for players, e.g. `wNc3`
+    /// See chessops::San
+    #[serde(default)]
+    pub notation: String,
+
     /// The resulting position using our custom FEN notation
     /// See chessops::fen module
     pub fen: String,
@@ -101,25 +379,250 @@ impl Default for Move {
     fn default() -> Self {
         Self {
             san: String::from(""),
+            notation: String::from(""),
             fen: Position::new_fen(),
             ts: Utc::now(),
         }
     }
 }
 
+/// Army ownership decoded from the FEN, so that clients do not have to parse it
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Armies {
+    /// 1 or 2
+    pub active_player: u8,
+    /// Username of the player to move, unless the game has not started or is over
+    pub to_move: Option<String>,
+    pub player1_armies: PlayerArmies,
+    pub player2_armies: PlayerArmies,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct PlayerArmies {
+    /// Not set until Player 2 accepts or rejects the first move
+    pub owned: Option<Color>,
+    /// Armies the player can defect to, sorted by name
+    pub controlled: Vec<Color>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GameWithoutMoves {
     pub pid: String,
     pub fen: String,
     pub state: GameState,
+    pub player1: Option<String>,
+    pub player2: Option<String>,
+    #[serde(default)]
+    pub result: Option<GameResult>,
+    #[serde(flatten)]
+    pub armies: Armies,
 }
 
 impl GameWithoutMoves {
     pub fn from_game(game: Game) -> Self {
         Self {
+            armies: game.armies(),
             pid: game.pid,
             fen: game.moves.last().unwrap().fen.clone(),
             state: game.state,
+            player1: game.player1,
+            player2: game.player2,
+            result: game.result,
+        }
+    }
+}
+
+/// A game along with its army ownership, as sent in websocket snapshots
+#[derive(Debug, Serialize)]
+pub struct GameWithArmies {
+    #[serde(flatten)]
+    pub game: Game,
+    #[serde(flatten)]
+    pub armies: Armies,
+}
+
+impl GameWithArmies {
+    pub fn from_game(game: Game) -> Self {
+        Self {
+            armies: game.armies(),
+            game,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chessops::{self, Move, Position};
+
+    fn play(game: &mut Game, san: &str) {
+        let mut pos = Position::from_fen(game.moves.last().unwrap().fen.clone());
+        pos.play_move(&Move::from_san(san)).unwrap();
+        game.add_move(pos.to_fen(), san.to_string(), String::new());
+    }
+
+    #[test]
+    fn take_back_works() {
+        let mut game = Game::new();
+        play(&mut game, "WNf01g03");
+        let mut pos = Position::from_fen(game.moves.last().unwrap().fen.clone());
+        pos.accept_first_move();
+        game.add_move(
+            pos.to_fen(),
+            "action:accept".to_string(),
+            "accept".to_string(),
+        );
+        game.state = GameState::InProgress;
+        play(&mut game, "BNf16g14");
+
+        // Accepting the first move swaps armies, so Player 1 also plays Black's first move
+        assert_eq!(game.takeback_count(1), Some(1));
+        assert_eq!(game.takeback_count(2), Some(2));
+
+        game.take_back(1);
+        assert_eq!(game.moves.len(), 3);
+        assert!(matches!(game.state, GameState::InProgress));
+
+        game.take_back(2);
+        assert!(matches!(game.state, GameState::Accepted));
+        assert_eq!(game.takeback_count(1), None);
+    }
+
+    #[test]
+    fn redacted_for_hides_opponents_premove() {
+        let mut game = Game::new();
+        game.player1 = Some("anon1".to_string());
+        game.player2 = Some("anon2".to_string());
+        game.premove = Some(Premove {
+            player: 2,
+            san: "BNf16g14".to_string(),
+        });
+
+        assert!(game.clone().redacted_for(Some("anon2")).premove.is_some());
+        assert!(game.clone().redacted_for(Some("anon1")).premove.is_none());
+        assert!(game.redacted_for(None).premove.is_none());
+    }
+
+    #[test]
+    fn can_join_works() {
+        let user = |name: &str| User {
+            name: name.to_string(),
+            webhook: None,
+        };
+        let mut game = Game::new();
+        game.player1 = Some("anon1".to_string());
+        assert!(game.can_join(&user("anon1"), None).is_err());
+        assert!(game.can_join(&user("anon2"), None).is_ok());
+
+        game.set_invite_token();
+        let token = game.invite_token.clone();
+        assert!(game.can_join(&user("anon2"), None).is_err());
+        assert!(game.can_join(&user("anon2"), token.as_deref()).is_ok());
+        assert!(game
+            .clone()
+            .redacted_for(Some("anon2"))
+            .invite_token
+            .is_none());
+        assert_eq!(game.clone().redacted_for(Some("anon1")).invite_token, token);
+
+        game.invite_token = None;
+        game.invited_player = Some("anon3".to_string());
+        assert!(game.can_join(&user("anon2"), None).is_err());
+        assert!(game.can_join(&user("anon3"), None).is_ok());
+    }
+
+    #[test]
+    fn set_creator_works() {
+        let user = |name: &str| User {
+            name: name.to_string(),
+            webhook: None,
+        };
+        let mut game = Game::new();
+        game.set_creator(&user("anon1"), PlayOrder::Second);
+        assert_eq!(game.player1, None);
+        assert!(game.can_join(&user("anon1"), None).is_err());
+
+        game.set_player_joined(&user("anon2"));
+        assert_eq!(game.player1, Some("anon2".to_string()));
+        assert_eq!(game.player2, Some("anon1".to_string()));
+
+        let mut game = Game::new();
+        game.set_creator(&user("anon1"), PlayOrder::Random);
+        game.set_player_joined(&user("anon2"));
+        assert!(game.player1.is_some() && game.player2.is_some());
+    }
+
+    #[test]
+    fn armies_works() {
+        let mut game = Game::new();
+        assert_eq!(game.armies().to_move, None);
+
+        game.player1 = Some("anon1".to_string());
+        game.set_player_joined(&User {
+            name: "anon2".to_string(),
+            webhook: None,
+        });
+        play(&mut game, "WNf01g03");
+        let armies = game.armies();
+        assert_eq!(armies.active_player, 2);
+        assert_eq!(armies.to_move, Some("anon2".to_string()));
+        assert_eq!(armies.player1_armies.owned, None);
+
+        let fen = "bk15/16/16/16/16/16/16/01nr14/16/16/16/16/16/16/16/wk15 1 w n b - 3";
+        let mut game = Game::from_fen(fen).unwrap();
+        game.player1 = Some("anon1".to_string());
+        game.set_player_joined(&User {
+            name: "anon2".to_string(),
+            webhook: None,
+        });
+        let armies = game.armies();
+        assert_eq!(armies.to_move, Some("anon1".to_string()));
+        assert_eq!(
+            armies.player1_armies,
+            PlayerArmies {
+                owned: Some(chessops::Color::White),
+                controlled: vec![chessops::Color::Navy],
+            }
+        );
+        assert_eq!(
+            serde_json::to_value(&armies.player2_armies).unwrap(),
+            serde_json::json!({"owned": "black", "controlled": []})
+        );
+    }
+
+    #[test]
+    fn from_fen_works() {
+        let board = "bk15/16/16/16/16/16/16/16/16/16/16/16/16/16/16/wkwq14";
+        assert!(Game::from_fen("16/16 1 w - b - 0").is_err());
+        assert!(Game::from_fen(&format!("{} 2 - - - - 0", board)).is_err());
+
+        let fen = format!("{} 1 w - b - 0", board);
+        let mut game = Game::from_fen(&fen).unwrap();
+        assert_eq!(game.start_fen(), fen);
+        game.set_player_joined(&User {
+            name: "anon1".to_string(),
+            webhook: None,
+        });
+        assert!(matches!(game.state, GameState::InProgress));
+
+        play(&mut game, "WQb01b05");
+        assert!(game.position_problems().is_empty());
+        game.moves[1].fen = game.moves[1].fen.replace(" w - b - ", " w - w - ");
+        assert_eq!(
+            game.position_problems(),
+            vec![
+                (1, PositionProblem::OwnedByBoth(chessops::Color::White)),
+                (1, PositionProblem::UnownedKing(chessops::Color::Black)),
+            ],
+        );
+        game.take_back(1);
+        assert!(matches!(game.state, GameState::InProgress));
+
+        let mut game = Game::from_fen(&format!("{} 1 - - - - 0", board)).unwrap();
+        game.set_player_joined(&User {
+            name: "anon1".to_string(),
+            webhook: None,
+        });
+        assert!(matches!(game.state, GameState::Accepted));
+    }
+}
@@ -3,10 +3,11 @@ use mongodb::Database;
 use serde::Serialize;
 use std::error::Error;
 use std::fmt;
+use std::sync::Arc;
 
-use crate::chessops;
 use crate::db;
-use crate::game::{Game, GameState};
+use crate::game::{Game, GameResult, GameState, Premove};
+use crate::notify::{self, NotificationSink};
 use crate::user::User;
 
 #[derive(Debug, Serialize)]
@@ -27,64 +28,62 @@ pub struct GameHandler {
     game: Game,
     user: User,
     db: Database,
+    notifier: Arc<dyn NotificationSink>,
 }
 
 impl GameHandler {
-    pub fn new(game: Game, user: User, db_handle: Database) -> Self {
-        match game.state {
-            GameState::Created => Self {
-                state: Some(Box::new(Created {})),
-                game: game,
-                user: user,
-                db: db_handle,
-            },
-            GameState::Accepted => Self {
-                state: Some(Box::new(Accepted {})),
-                game: game,
-                user: user,
-                db: db_handle,
-            },
-            GameState::FirstMove => Self {
-                state: Some(Box::new(FirstMove {})),
-                game: game,
-                user: user,
-                db: db_handle,
-            },
-            GameState::InProgress => Self {
-                state: Some(Box::new(InProgress {})),
-                game: game,
-                user: user,
-                db: db_handle,
-            },
-            GameState::DefectMoveKing => Self {
-                state: Some(Box::new(DefectMoveKing {})),
-                game: game,
-                user: user,
-                db: db_handle,
-            },
-            _ => todo!(),
+    pub fn new(
+        game: Game,
+        user: User,
+        db_handle: Database,
+        notifier: Arc<dyn NotificationSink>,
+    ) -> Self {
+        Self {
+            state: Some(handler_state(&game.state)),
+            game,
+            user,
+            db: db_handle,
+            notifier,
         }
     }
 
-    pub fn read(&self, message: &str) -> Result<serde_json::Value, serde_json::Error> {
-        // Parse json string
-        serde_json::from_str(message)
+    /// The game, including any changes made by `process`
+    pub fn game(&self) -> &Game {
+        &self.game
     }
 
+    /// Apply a client message, then notify players whose turn it became or whose game ended
     pub async fn process(&mut self, json: serde_json::Value) -> Result<(), GameHandlerError> {
+        let before = self.game.clone();
+        self.dispatch(json).await?;
+
+        for (username, notification) in notify::notifications(&before, &self.game, &self.user.name)
+        {
+            self.notifier.notify(&username, notification).await;
+        }
+        Ok(())
+    }
+
+    async fn dispatch(&mut self, json: serde_json::Value) -> Result<(), GameHandlerError> {
         match json["t"].as_str() {
             Some("join") => {
                 if let Some(s) = self.state.take() {
-                    self.state = Some(s.join_game(self).await.expect("Failed to join game"));
+                    // Only needed for private games
+                    let token = json["d"]["token"].as_str().map(|t| t.to_string());
+                    match s.join_game(self, token).await {
+                        Ok(new_state) => {
+                            self.state = Some(new_state);
+                        }
+                        Err(err) => {
+                            return Err(err);
+                        }
+                    }
                 }
                 Ok(())
             }
             Some("first_move") => {
+                let san = required_str(&json["d"]["san"], "SAN")?.to_string();
                 if let Some(s) = self.state.take() {
-                    let san = json["d"]["san"]
-                        .as_str()
-                        .expect("Cannot find SAN in data object")
-                        .to_string();
                     match s.play_first_move(self, san).await {
                         Ok(new_state) => {
                             self.state = Some(new_state);
@@ -97,10 +96,8 @@ impl GameHandler {
                 Ok(())
             }
             Some("first_move_choice") => {
+                let choice = required_str(&json["d"], "choice for the first move")?;
                 if let Some(s) = self.state.take() {
-                    let choice = json["d"]
-                        .as_str()
-                        .expect("Cannot find choice for first move");
                     match s.choose_first_move(self, choice).await {
                         Ok(new_state) => {
                             self.state = Some(new_state);
@@ -113,11 +110,8 @@ impl GameHandler {
                 Ok(())
             }
             Some("move") => {
+                let san = required_str(&json["d"]["san"], "SAN")?.to_string();
                 if let Some(s) = self.state.take() {
-                    let san = json["d"]["san"]
-                        .as_str()
-                        .expect("Cannot find SAN in data object")
-                        .to_string();
                     match s.play_move(self, san).await {
                         Ok(new_state) => {
                             self.state = Some(new_state);
@@ -130,8 +124,8 @@ impl GameHandler {
                 Ok(())
             }
             Some("defect") => {
+                let color = required_str(&json["d"], "color to defect to")?;
                 if let Some(s) = self.state.take() {
-                    let color = json["d"].as_str().expect("Cannot find color to defect to");
                     match s.defect_to(self, color).await {
                         Ok(new_state) => {
                             self.state = Some(new_state);
@@ -143,11 +137,140 @@ impl GameHandler {
                 }
                 Ok(())
             }
+            Some("premove") => {
+                if let Some(s) = self.state.take() {
+                    // `null` cancels the premove
+                    let san = json["d"]["san"].as_str().map(|s| s.to_string());
+                    match s.set_premove(self, san).await {
+                        Ok(new_state) => {
+                            self.state = Some(new_state);
+                        }
+                        Err(err) => {
+                            return Err(err);
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Some("takeback") => {
+                if let Some(s) = self.state.take() {
+                    match s.request_takeback(self).await {
+                        Ok(new_state) => {
+                            self.state = Some(new_state);
+                        }
+                        Err(err) => {
+                            return Err(err);
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Some("takeback_choice") => {
+                let choice = required_str(&json["d"], "choice for the takeback")?;
+                if let Some(s) = self.state.take() {
+                    match s.choose_takeback(self, choice).await {
+                        Ok(new_state) => {
+                            self.state = Some(new_state);
+                        }
+                        Err(err) => {
+                            return Err(err);
+                        }
+                    }
+                }
+                Ok(())
+            }
             _ => Err(GameHandlerError {
                 message: "Invalid message type".to_string(),
             }),
         }
     }
+
+    /// The user's player number, if they are playing this game
+    fn player(&self) -> Option<u8> {
+        if self.game.player1.as_ref() == Some(&self.user.name) {
+            Some(1)
+        } else if self.game.player2.as_ref() == Some(&self.user.name) {
+            Some(2)
+        } else {
+            None
+        }
+    }
+
+    async fn request_takeback(&mut self) -> Result<(), GameHandlerError> {
+        if self.game.rated {
+            return Err(GameHandlerError {
+                message: "Takebacks are not allowed in rated games".to_string(),
+            });
+        }
+        let Some(player) = self.player() else {
+            return Err(GameHandlerError {
+                message: "Only players can ask for a takeback".to_string(),
+            });
+        };
+        if self.game.takeback_by.is_some() {
+            return Err(GameHandlerError {
+                message: "A takeback has already been requested".to_string(),
+            });
+        }
+        if self.game.takeback_count(player).is_none() {
+            return Err(GameHandlerError {
+                message: "You have no move to take back".to_string(),
+            });
+        }
+
+        self.game.takeback_by = Some(player);
+        db::save_takeback_request(&self.db, &self.game).await;
+        Ok(())
+    }
+
+    async fn choose_takeback(&mut self, choice: &str) -> Result<(), GameHandlerError> {
+        let Some(requested_by) = self.game.takeback_by else {
+            return Err(GameHandlerError {
+                message: "No takeback has been requested".to_string(),
+            });
+        };
+        // Only the requester's opponent can answer
+        if self.player().is_none() || self.player() == Some(requested_by) {
+            return Err(GameHandlerError {
+                message: "Not your takeback to answer".to_string(),
+            });
+        }
+
+        match choice {
+            "accept" => {
+                let prev_len = self.game.moves.len();
+                let count = self.game.takeback_count(requested_by).unwrap_or(0);
+                self.game.take_back(count);
+                if !db::save_takeback(&self.db, &self.game, prev_len).await {
+                    return Err(GameHandlerError {
+                        message: "The game changed, takeback cancelled".to_string(),
+                    });
+                }
+            }
+            "decline" => {
+                self.game.takeback_by = None;
+                db::save_takeback_request(&self.db, &self.game).await;
+            }
+            _ => {
+                return Err(GameHandlerError {
+                    message: "Invalid takeback choice".to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn handler_state(state: &GameState) -> Box<dyn HandlerState + Send + Sync> {
+    match state {
+        GameState::Created => Box::new(Created {}),
+        GameState::Accepted => Box::new(Accepted {}),
+        GameState::FirstMove => Box::new(FirstMove {}),
+        GameState::InProgress => Box::new(InProgress {}),
+        GameState::DefectMoveKing => Box::new(DefectMoveKing {}),
+        GameState::Ended => Box::new(Ended {}),
+        GameState::Cancelled => Box::new(Cancelled {}),
+    }
 }
 
 #[async_trait]
@@ -156,7 +279,8 @@ trait HandlerState {
     async fn join_game(
         &self,
         handler: &mut GameHandler,
-    ) -> Result<Box<Accepted>, GameHandlerError> {
+        token: Option<String>,
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
         Err(GameHandlerError {
             message: "Forbidden game action".to_string(),
         })
@@ -189,7 +313,7 @@ trait HandlerState {
         &self,
         handler: &mut GameHandler,
         san: String,
-    ) -> Result<Box<InProgress>, GameHandlerError> {
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
         Err(GameHandlerError {
             message: "Forbidden game action".to_string(),
         })
@@ -205,6 +329,45 @@ trait HandlerState {
             message: "Forbidden game action".to_string(),
         })
     }
+
+    #[allow(unused_variables)]
+    async fn set_premove(
+        &self,
+        handler: &mut GameHandler,
+        san: Option<String>,
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
+        Err(GameHandlerError {
+            message: "Forbidden game action".to_string(),
+        })
+    }
+
+    #[allow(unused_variables)]
+    async fn request_takeback(
+        &self,
+        handler: &mut GameHandler,
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
+        Err(GameHandlerError {
+            message: "Forbidden game action".to_string(),
+        })
+    }
+
+    #[allow(unused_variables)]
+    async fn choose_takeback(
+        &self,
+        handler: &mut GameHandler,
+        choice: &str,
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
+        Err(GameHandlerError {
+            message: "Forbidden game action".to_string(),
+        })
+    }
+}
+
+/// The string `value` holds, or an error saying which `what` the client left out
+fn required_str<'a>(value: &'a serde_json::Value, what: &str) -> Result<&'a str, GameHandlerError> {
+    value.as_str().ok_or_else(|| GameHandlerError {
+        message: format!("Missing {}", what),
+    })
 }
 
 struct Created {}
@@ -212,16 +375,29 @@ struct Accepted {}
 struct FirstMove {}
 struct InProgress {}
 struct DefectMoveKing {}
+struct Ended {}
+struct Cancelled {}
 
 #[async_trait]
 impl HandlerState for Created {
     async fn join_game(
         &self,
         handler: &mut GameHandler,
-    ) -> Result<Box<Accepted>, GameHandlerError> {
+        token: Option<String>,
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
+        if let Err(message) = handler.game.can_join(&handler.user, token.as_deref()) {
+            return Err(GameHandlerError {
+                message: message.to_string(),
+            });
+        }
         handler.game.set_player_joined(&handler.user);
-        db::update_player(&handler.db, &handler.game, &handler.user.name).await;
-        Ok(Box::new(Accepted {}))
+        if !db::update_player(&handler.db, &handler.game, &handler.user.name).await {
+            return Err(GameHandlerError {
+                message: "Could not join the game, somebody else may have joined first".to_string(),
+            });
+        }
+        // Custom positions may skip the first move
+        Ok(handler_state(&handler.game.state))
     }
 }
 
@@ -242,10 +418,12 @@ impl HandlerState for Accepted {
                 message: "Not your turn".to_string(),
             })
         } else {
-            let chess_move = chessops::Move::from_san(&san);
+            let chess_move = parse_move(&pos, &san)?;
+            let pos_before = pos.clone();
             match pos.play_move(&chess_move) {
                 Ok(new_pos) => {
-                    game.add_move(new_pos.to_fen(), san.clone());
+                    let notation = chessops::San::from_move(&pos_before, &chess_move);
+                    game.add_move(new_pos.to_fen(), chess_move.to_san(), notation);
                     game.state = GameState::FirstMove;
                     db::save_game_move(&handler.db, &handler.game).await;
 
@@ -291,20 +469,81 @@ impl HandlerState for FirstMove {
         // Save game move
         game.state = GameState::InProgress;
         let san = format!("action:{}", choice);
-        game.add_move(new_pos.to_fen(), san);
+        game.add_move(new_pos.to_fen(), san, choice.to_string());
         db::save_game_move(&handler.db, &handler.game).await;
 
         Ok(Box::new(InProgress {}))
     }
+
+    async fn request_takeback(
+        &self,
+        handler: &mut GameHandler,
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
+        handler.request_takeback().await?;
+        Ok(handler_state(&handler.game.state))
+    }
+
+    async fn choose_takeback(
+        &self,
+        handler: &mut GameHandler,
+        choice: &str,
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
+        handler.choose_takeback(choice).await?;
+        Ok(handler_state(&handler.game.state))
+    }
 }
 
 #[async_trait]
 impl HandlerState for InProgress {
+    async fn set_premove(
+        &self,
+        handler: &mut GameHandler,
+        san: Option<String>,
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
+        let current_fen = handler.game.moves.last().unwrap().fen.clone();
+        let pos = chessops::Position::from_fen(current_fen);
+
+        let Some(player) = handler.player() else {
+            return Err(GameHandlerError {
+                message: "Only players can premove".to_string(),
+            });
+        };
+        if pos.active_player() == player {
+            return Err(GameHandlerError {
+                message: "It is your turn, play the move instead".to_string(),
+            });
+        }
+
+        handler.game.premove = match san {
+            Some(san) => {
+                let chess_move = chessops::Move::try_from_san(&san).ok_or(GameHandlerError {
+                    message: san_error_to_str(chessops::SanError::InvalidNotation).to_string(),
+                })?;
+                // Legality is only known once the opponent has moved, but the piece has to be ours
+                if pos.board().get(&chess_move.from) != Some(&chess_move.to_piece())
+                    || !pos.armies(player).contains(&chess_move.color)
+                {
+                    return Err(GameHandlerError {
+                        message: error_to_str(chessops::PositionError::NotOwnColor).to_string(),
+                    });
+                }
+                Some(Premove {
+                    player,
+                    san: chess_move.to_san(),
+                })
+            }
+            None => None,
+        };
+        db::save_premove(&handler.db, &handler.game).await;
+
+        Ok(Box::new(InProgress {}))
+    }
+
     async fn play_move(
         &self,
         handler: &mut GameHandler,
         san: String,
-    ) -> Result<Box<InProgress>, GameHandlerError> {
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
         let game = &mut handler.game;
         let user = &handler.user;
         let current_fen = game.moves.last().unwrap().fen.clone();
@@ -315,14 +554,25 @@ impl HandlerState for InProgress {
                 message: "Not your turn".to_string(),
             })
         } else {
-            let chess_move = chessops::Move::from_san(&san);
+            let chess_move = parse_move(&pos, &san)?;
+            let pos_before = pos.clone();
             match pos.play_move(&chess_move) {
                 Ok(new_pos) => {
-                    game.add_move(new_pos.to_fen(), san.clone());
-                    game.state = GameState::InProgress;
-                    db::save_game_move(&handler.db, &handler.game).await;
+                    let notation = chessops::San::from_move(&pos_before, &chess_move);
+                    let is_checkmate = new_pos.is_checkmate();
+                    game.add_move(new_pos.to_fen(), chess_move.to_san(), notation);
 
-                    Ok(Box::new(InProgress {}))
+                    if is_checkmate {
+                        game.set_ended(GameResult::win_for(pos_before.active_player()));
+                        db::save_game_move(&handler.db, &handler.game).await;
+
+                        Ok(Box::new(Ended {}))
+                    } else {
+                        game.state = GameState::InProgress;
+                        db::save_game_move(&handler.db, &handler.game).await;
+
+                        Ok(play_premove(handler).await)
+                    }
                 }
                 Err(err) => Err(GameHandlerError {
                     message: error_to_str(err).to_string(),
@@ -346,21 +596,25 @@ impl HandlerState for InProgress {
                 message: "Not your turn".to_string(),
             })
         } else {
-            let color = chessops::Color::from_str(color_str).expect("Invalid color code");
+            let Some(color) = chessops::Color::from_str(color_str) else {
+                return Err(GameHandlerError {
+                    message: format!("Invalid color: {}", color_str),
+                });
+            };
             match pos.defect_to(color) {
                 Ok(()) => {
                     game.state = GameState::InProgress;
                     let san = format!("action:defect:{}", color_str.to_lowercase());
-                    game.add_move(pos.to_fen(), san);
+                    game.add_move(pos.to_fen(), san, chessops::San::from_defect(&color));
                     db::save_game_move(&handler.db, &game).await;
 
-                    Ok(Box::new(InProgress {}))
+                    Ok(play_premove(handler).await)
                 }
                 Err(err) => match err {
                     chessops::PositionError::DefectMoveKing => {
                         game.state = GameState::DefectMoveKing;
                         let san = format!("action:defect:{}*", color_str.to_lowercase());
-                        game.add_move(pos.to_fen(), san);
+                        game.add_move(pos.to_fen(), san, chessops::San::from_defect(&color));
                         db::save_game_move(&handler.db, &game).await;
 
                         Ok(Box::new(DefectMoveKing {}))
@@ -374,15 +628,38 @@ impl HandlerState for InProgress {
             }
         }
     }
+
+    async fn request_takeback(
+        &self,
+        handler: &mut GameHandler,
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
+        handler.request_takeback().await?;
+        Ok(handler_state(&handler.game.state))
+    }
+
+    async fn choose_takeback(
+        &self,
+        handler: &mut GameHandler,
+        choice: &str,
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
+        handler.choose_takeback(choice).await?;
+        Ok(handler_state(&handler.game.state))
+    }
 }
 
+#[async_trait]
+impl HandlerState for Ended {}
+
+#[async_trait]
+impl HandlerState for Cancelled {}
+
 #[async_trait]
 impl HandlerState for DefectMoveKing {
     async fn play_move(
         &self,
         handler: &mut GameHandler,
         san: String,
-    ) -> Result<Box<InProgress>, GameHandlerError> {
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
         let game = &mut handler.game;
         let user = &handler.user;
         let current_fen = game.moves.last().unwrap().fen.clone();
@@ -393,14 +670,25 @@ impl HandlerState for DefectMoveKing {
                 message: "Not your turn".to_string(),
             })
         } else {
-            let chess_move = chessops::Move::from_san(&san);
+            let chess_move = parse_move(&pos, &san)?;
+            let pos_before = pos.clone();
             match pos.play_move_after_defect(&chess_move) {
                 Ok(new_pos) => {
-                    game.add_move(new_pos.to_fen(), san.clone());
-                    game.state = GameState::InProgress;
-                    db::save_game_move(&handler.db, &handler.game).await;
+                    let notation = chessops::San::from_move(&pos_before, &chess_move);
+                    let is_checkmate = new_pos.is_checkmate();
+                    game.add_move(new_pos.to_fen(), chess_move.to_san(), notation);
 
-                    Ok(Box::new(InProgress {}))
+                    if is_checkmate {
+                        game.set_ended(GameResult::win_for(pos_before.active_player()));
+                        db::save_game_move(&handler.db, &handler.game).await;
+
+                        Ok(Box::new(Ended {}))
+                    } else {
+                        game.state = GameState::InProgress;
+                        db::save_game_move(&handler.db, &handler.game).await;
+
+                        Ok(play_premove(handler).await)
+                    }
                 }
                 Err(err) => Err(GameHandlerError {
                     message: error_to_str(err).to_string(),
@@ -408,9 +696,86 @@ impl HandlerState for DefectMoveKing {
             }
         }
     }
+
+    async fn request_takeback(
+        &self,
+        handler: &mut GameHandler,
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
+        handler.request_takeback().await?;
+        Ok(handler_state(&handler.game.state))
+    }
+
+    async fn choose_takeback(
+        &self,
+        handler: &mut GameHandler,
+        choice: &str,
+    ) -> Result<Box<dyn HandlerState + Send + Sync>, GameHandlerError> {
+        handler.choose_takeback(choice).await?;
+        Ok(handler_state(&handler.game.state))
+    }
+}
+
+/// Play the premove queued by the player whose turn it now is, dropping it if it has become
+/// illegal.  Returns the state the game ends up in.
+async fn play_premove(handler: &mut GameHandler) -> Box<dyn HandlerState + Send + Sync> {
+    let game = &mut handler.game;
+    let current_fen = game.moves.last().unwrap().fen.clone();
+    let mut pos = chessops::Position::from_fen(current_fen);
+
+    let Some(premove) = game.premove.take() else {
+        return handler_state(&game.state);
+    };
+    if premove.player != pos.active_player() {
+        // Still the opponent's turn, e.g. they defected and must now move their King
+        game.premove = Some(premove);
+        return handler_state(&game.state);
+    }
+
+    let pos_before = pos.clone();
+    let played = chessops::Move::try_from_san(&premove.san).filter(|m| pos.play_move(m).is_ok());
+    let Some(chess_move) = played else {
+        db::save_premove(&handler.db, game).await;
+        return handler_state(&game.state);
+    };
+
+    let notation = chessops::San::from_move(&pos_before, &chess_move);
+    game.add_move(pos.to_fen(), chess_move.to_san(), notation);
+    if pos.is_checkmate() {
+        game.set_ended(GameResult::win_for(pos_before.active_player()));
+    }
+    db::save_game_move(&handler.db, game).await;
+
+    handler_state(&game.state)
 }
 
-fn error_to_str(err: chessops::PositionError) -> &'static str {
+/// Accept both the long form `Move::from_san` understands and our short notation
+fn parse_move(pos: &chessops::Position, san: &str) -> Result<chessops::Move, GameHandlerError> {
+    if san.starts_with(|c: char| c.is_ascii_uppercase()) {
+        return chessops::Move::try_from_san(san).ok_or(GameHandlerError {
+            message: san_error_to_str(chessops::SanError::InvalidNotation).to_string(),
+        });
+    }
+
+    match chessops::San::parse(pos, san) {
+        Ok(chessops::SanAction::Move(chess_move)) => Ok(chess_move),
+        Ok(chessops::SanAction::Defect(_)) => Err(GameHandlerError {
+            message: "Use the defect action to defect".to_string(),
+        }),
+        Err(err) => Err(GameHandlerError {
+            message: san_error_to_str(err).to_string(),
+        }),
+    }
+}
+
+pub(crate) fn san_error_to_str(err: chessops::SanError) -> &'static str {
+    match err {
+        chessops::SanError::InvalidNotation => "Invalid move notation",
+        chessops::SanError::IllegalMove => "Illegal move",
+        chessops::SanError::AmbiguousMove => "Ambiguous move, specify the origin square",
+    }
+}
+
+pub(crate) fn error_to_str(err: chessops::PositionError) -> &'static str {
     match err {
         chessops::PositionError::IllegalMove => "Illegal move",
         chessops::PositionError::NotOwnColor => "You do not control that piece",
@@ -422,3 +787,48 @@ fn error_to_str(err: chessops::PositionError) -> &'static str {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::WebhookSink;
+    use mongodb::{options::ClientOptions, Client};
+
+    #[test]
+    fn missing_payload_fields_are_errors() {
+        let json = serde_json::json!({ "t": "move", "d": { "san": "e4" } });
+        assert_eq!(required_str(&json["d"]["san"], "SAN").unwrap(), "e4");
+        let err = required_str(&json["d"]["uci"], "UCI").unwrap_err();
+        assert_eq!(err.message, "Missing UCI");
+        assert!(required_str(&json["d"], "color").is_err());
+        assert_eq!(chessops::Color::from_str(""), None);
+    }
+
+    #[tokio::test]
+    async fn rated_games_refuse_takebacks() {
+        // Never connects, the takeback is refused before anything is saved
+        let options = ClientOptions::parse("mongodb://localhost:27017")
+            .await
+            .unwrap();
+        let db = Client::with_options(options).unwrap().database("test");
+
+        let mut game = Game::new();
+        game.player1 = Some("anon1".to_string());
+        game.player2 = Some("anon2".to_string());
+        game.state = GameState::InProgress;
+        game.rated = true;
+        let user = User {
+            name: "anon1".to_string(),
+            webhook: None,
+        };
+        let notifier = Arc::new(WebhookSink::new(db.clone()));
+        let mut handler = GameHandler::new(game, user, db, notifier);
+
+        let err = handler
+            .process(serde_json::json!({"t": "takeback"}))
+            .await
+            .unwrap_err();
+        assert_eq!(err.message, "Takebacks are not allowed in rated games");
+        assert!(handler.game().takeback_by.is_none());
+    }
+}
@@ -0,0 +1,395 @@
+use chessops::Position;
+use chrono::{DateTime, Utc};
+use mongodb::bson::{self, doc, Bson, Document};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::game::{Game, GameResult, GameState, GameWithoutMoves};
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+
+/// Which games to list, by state
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum StateFilter {
+    /// Both players have joined and the game has not ended
+    InProgress,
+    Ended,
+    /// Created, nobody has joined yet
+    Waiting,
+}
+
+impl StateFilter {
+    fn states(self) -> Vec<GameState> {
+        match self {
+            StateFilter::InProgress => vec![
+                GameState::Accepted,
+                GameState::FirstMove,
+                GameState::InProgress,
+                GameState::DefectMoveKing,
+            ],
+            StateFilter::Ended => vec![GameState::Ended],
+            StateFilter::Waiting => vec![GameState::Created],
+        }
+    }
+}
+
+/**
+ * Query parameters of `GET /api/games`, e.g. `?state=InProgress&my_turn=true&limit=10`
+ *
+ * Games are listed most recently updated first.  To get the next page, pass the previous
+ * response's `next_cursor` as `cursor` along with the same filters.
+ */
+#[derive(Debug, Default, Deserialize)]
+pub struct GameListParams {
+    state: Option<StateFilter>,
+    /// Only games against this user
+    opponent: Option<String>,
+    /// Only games waiting on the user's move
+    #[serde(default)]
+    my_turn: bool,
+    cursor: Option<String>,
+    /// Games per page, at most `MAX_LIMIT`
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GamePage {
+    games: Vec<GameWithoutMoves>,
+    /// Not set on the last page
+    next_cursor: Option<String>,
+}
+
+/// Position in the listing: the `updated` time in milliseconds and `pid` of the last game
+/// on the previous page, e.g. `1718000000000.a1b2c3d4e5f6`
+#[derive(Debug, PartialEq)]
+struct Cursor {
+    updated: i64,
+    pid: String,
+}
+
+impl Cursor {
+    fn from_game(game: &Game) -> Self {
+        Self {
+            updated: game.updated.timestamp_millis(),
+            pid: game.pid.clone(),
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        let (updated, pid) = s.split_once('.')?;
+        if pid.is_empty() || !pid.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        Some(Self {
+            updated: updated.parse().ok()?,
+            pid: pid.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.updated, self.pid)
+    }
+}
+
+/**
+ * Query parameters of `GET /api/games/search`, which lists finished public games, e.g.
+ * `?player=anon1&result=Player1Won&from=2024-06-01T00:00:00Z&min_moves=20`
+ *
+ * Pages work the same as for `GameListParams`.
+ */
+#[derive(Debug, Default, Deserialize)]
+pub struct GameSearchParams {
+    /// Only games this user played, on either side
+    player: Option<String>,
+    /// Only games that ended at or after this time, in RFC 3339
+    from: Option<DateTime<Utc>>,
+    /// Only games that ended before this time
+    to: Option<DateTime<Utc>>,
+    result: Option<GameResult>,
+    /// Bounds on the number of entries in `moves` after the starting position, which
+    /// includes defections and Player 2's choice after the first move
+    min_moves: Option<u32>,
+    max_moves: Option<u32>,
+    /// Only games that started from this position, e.g. `Position::new_fen()`
+    fen: Option<String>,
+    cursor: Option<String>,
+    /// Games per page, at most `MAX_LIMIT`
+    limit: Option<i64>,
+}
+
+fn page_limit(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+/// Only the games after `cursor`, in the order of `db::find_games`
+fn cursor_clause(cursor: &str) -> Result<Document, &'static str> {
+    let cursor = Cursor::parse(cursor).ok_or("Invalid cursor")?;
+    let updated = bson::DateTime::from_millis(cursor.updated);
+    // Same order as the sort: `updated`, then `pid`, both descending
+    Ok(doc! {
+        "$or": [
+            {"updated": {"$lt": updated}},
+            {"updated": updated, "pid": {"$lt": cursor.pid}},
+        ],
+    })
+}
+
+impl GameListParams {
+    pub fn limit(&self) -> i64 {
+        page_limit(self.limit)
+    }
+
+    /// The Mongo filter for `username`'s games.  Fails if the cursor is malformed.
+    pub fn filter(&self, username: &str) -> Result<Document, &'static str> {
+        let mut clauses = vec![doc! {
+            "$or": [
+                {"player1": username},
+                {"player2": username},
+                {"invited_player": username},
+            ],
+        }];
+
+        if let Some(state) = self.state {
+            let states: Vec<Bson> = state
+                .states()
+                .iter()
+                .map(|s| bson::to_bson(s).unwrap())
+                .collect();
+            clauses.push(doc! { "state": { "$in": states } });
+        }
+        if let Some(opponent) = &self.opponent {
+            clauses.push(doc! {
+                "$or": [
+                    {"player1": username, "player2": opponent},
+                    {"player1": opponent, "player2": username},
+                    {"player1": username, "invited_player": opponent},
+                    {"player2": username, "invited_player": opponent},
+                    {"invited_player": username, "player1": opponent},
+                    {"invited_player": username, "player2": opponent},
+                ],
+            });
+        }
+        if self.my_turn {
+            clauses.push(doc! { "waiting_on": username });
+        }
+        if let Some(cursor) = &self.cursor {
+            clauses.push(cursor_clause(cursor)?);
+        }
+
+        Ok(doc! { "$and": clauses })
+    }
+}
+
+impl GameSearchParams {
+    pub fn limit(&self) -> i64 {
+        page_limit(self.limit)
+    }
+
+    /// The Mongo filter for finished games, leaving out private and imported ones.  Fails if
+    /// the cursor or FEN is malformed or the bounds are empty or too large.
+    pub fn filter(&self) -> Result<Document, &'static str> {
+        let mut clauses = vec![doc! {
+            "state": bson::to_bson(&GameState::Ended).unwrap(),
+            // Also matches games without the field
+            "invite_token": Bson::Null,
+            "imported": { "$ne": true },
+        }];
+
+        if let Some(player) = &self.player {
+            clauses.push(doc! { "$or": [{"player1": player}, {"player2": player}] });
+        }
+        if self.from.is_some() || self.to.is_some() {
+            if matches!((self.from, self.to), (Some(from), Some(to)) if from >= to) {
+                return Err("`from` must be before `to`");
+            }
+            let mut range = Document::new();
+            if let Some(from) = self.from {
+                range.insert("$gte", bson::DateTime::from_chrono(from));
+            }
+            if let Some(to) = self.to {
+                range.insert("$lt", bson::DateTime::from_chrono(to));
+            }
+            clauses.push(doc! { "updated": range });
+        }
+        if let Some(result) = &self.result {
+            clauses.push(doc! { "result": bson::to_bson(result).unwrap() });
+        }
+        // `moves[0]` is the starting position, so at least n moves means `moves[n]` exists
+        if let Some(min) = self.min_moves {
+            clauses.push(doc! { format!("moves.{}", min): { "$exists": true } });
+        }
+        if let Some(max) = self.max_moves {
+            if self.min_moves.is_some_and(|min| min > max) {
+                return Err("`min_moves` must not exceed `max_moves`");
+            }
+            let after_max = max.checked_add(1).ok_or("`max_moves` is too large")?;
+            clauses.push(doc! { format!("moves.{}", after_max): { "$exists": false } });
+        }
+        if let Some(fen) = &self.fen {
+            // Stored FENs are the ones we wrote, so match on the same spelling
+            let fen = Position::try_from_fen(fen)
+                .map_err(|_| "`fen` is not a valid position")?
+                .to_fen();
+            clauses.push(doc! { "moves.0.fen": fen });
+        }
+        if let Some(cursor) = &self.cursor {
+            clauses.push(cursor_clause(cursor)?);
+        }
+
+        Ok(doc! { "$and": clauses })
+    }
+}
+
+impl GamePage {
+    /// `games` as returned by `db::find_games` with a limit of one more than `limit`, so that
+    /// we know whether there is a next page
+    pub fn new(mut games: Vec<Game>, limit: i64) -> Self {
+        let next_cursor = if games.len() as i64 > limit {
+            games.truncate(limit as usize);
+            games.last().map(|game| Cursor::from_game(game).to_string())
+        } else {
+            None
+        };
+        Self {
+            games: games.into_iter().map(GameWithoutMoves::from_game).collect(),
+            next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_parse_works() {
+        let cursor = Cursor::parse("1718000000000.a1b2c3d4e5f6").unwrap();
+        assert_eq!(cursor.updated, 1718000000000);
+        assert_eq!(cursor.pid, "a1b2c3d4e5f6");
+        assert_eq!(cursor.to_string(), "1718000000000.a1b2c3d4e5f6");
+
+        assert!(Cursor::parse("1718000000000").is_none());
+        assert!(Cursor::parse("abc.a1b2c3d4e5f6").is_none());
+        assert!(Cursor::parse("1718000000000.").is_none());
+        assert!(Cursor::parse("1718000000000.{\"$ne\":1}").is_none());
+    }
+
+    #[test]
+    fn filter_works() {
+        let params = GameListParams {
+            state: Some(StateFilter::Waiting),
+            my_turn: true,
+            ..Default::default()
+        };
+        let filter = params.filter("anon1").unwrap();
+        let clauses = filter.get_array("$and").unwrap();
+        assert_eq!(clauses.len(), 3);
+        assert_eq!(
+            clauses[1],
+            Bson::Document(doc! { "state": { "$in": ["Created"] } })
+        );
+        assert_eq!(clauses[2], Bson::Document(doc! { "waiting_on": "anon1" }));
+
+        let params = GameListParams {
+            cursor: Some("not a cursor".to_string()),
+            ..Default::default()
+        };
+        assert!(params.filter("anon1").is_err());
+    }
+
+    #[test]
+    fn search_filter_works() {
+        let params = GameSearchParams {
+            player: Some("anon1".to_string()),
+            result: Some(GameResult::Draw),
+            min_moves: Some(2),
+            max_moves: Some(10),
+            ..Default::default()
+        };
+        let filter = params.filter().unwrap();
+        let clauses = filter.get_array("$and").unwrap();
+        assert_eq!(clauses.len(), 5);
+        assert_eq!(
+            clauses[0],
+            Bson::Document(doc! {
+                "state": "Ended",
+                "invite_token": Bson::Null,
+                "imported": { "$ne": true },
+            })
+        );
+        assert_eq!(clauses[2], Bson::Document(doc! { "result": "Draw" }));
+        assert_eq!(
+            clauses[3],
+            Bson::Document(doc! { "moves.2": { "$exists": true } })
+        );
+        assert_eq!(
+            clauses[4],
+            Bson::Document(doc! { "moves.11": { "$exists": false } })
+        );
+
+        // Equivalent spellings of the same position find the same games
+        let params = GameSearchParams {
+            fen: Some(Position::new_fen().replacen("12", "0606", 1)),
+            ..Default::default()
+        };
+        let clauses = params.filter().unwrap();
+        assert_eq!(
+            clauses.get_array("$and").unwrap()[1],
+            Bson::Document(doc! { "moves.0.fen": Position::new_fen() })
+        );
+
+        let params = GameSearchParams {
+            fen: Some("not a fen".to_string()),
+            ..Default::default()
+        };
+        assert!(params.filter().is_err());
+
+        let params = GameSearchParams {
+            min_moves: Some(10),
+            max_moves: Some(2),
+            ..Default::default()
+        };
+        assert!(params.filter().is_err());
+
+        let now = Utc::now();
+        let params = GameSearchParams {
+            from: Some(now),
+            to: Some(now),
+            ..Default::default()
+        };
+        assert!(params.filter().is_err());
+
+        let params = GameSearchParams {
+            max_moves: Some(u32::MAX),
+            ..Default::default()
+        };
+        assert!(params.filter().is_err());
+    }
+
+    #[test]
+    fn page_works() {
+        let games: Vec<Game> = (0..3).map(|_| Game::new()).collect();
+        let last_pid = games[1].pid.clone();
+
+        let page = GamePage::new(games, 2);
+        assert_eq!(page.games.len(), 2);
+        assert!(page.next_cursor.unwrap().ends_with(&last_pid));
+
+        let page = GamePage::new(vec![Game::new()], 2);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[test]
+    fn limit_is_clamped() {
+        let params = |limit| GameListParams {
+            limit,
+            ..Default::default()
+        };
+        assert_eq!(params(None).limit(), DEFAULT_LIMIT);
+        assert_eq!(params(Some(0)).limit(), 1);
+        assert_eq!(params(Some(1000)).limit(), MAX_LIMIT);
+    }
+}
@@ -1,19 +1,26 @@
 use axum::{
     extract::{ws::WebSocketUpgrade, Path, Query, State},
     http::{
-        header::{HeaderMap, AUTHORIZATION},
+        header::{HeaderMap, AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE},
         StatusCode,
     },
-    response::Response,
+    response::{IntoResponse, Response},
     Json,
 };
-use futures::StreamExt;
 use mongodb::{bson::doc, Database};
+use serde::Deserialize;
+use serde_json::json;
 use std::collections::HashMap;
 use tracing::error;
 
+use crate::analysis;
 use crate::db;
-use crate::game::{Game, GameWithoutMoves};
+use crate::explorer;
+use crate::game::{Game, GameState, PlayOrder};
+use crate::game_list::{GameListParams, GamePage, GameSearchParams};
+use crate::notify::{Notification, Webhook};
+use crate::record;
+use crate::render;
 use crate::state::SharedState;
 use crate::user::User;
 use crate::websocket;
@@ -33,16 +40,15 @@ pub async fn handle_websocket_play_game(
             return Err(StatusCode::UNAUTHORIZED);
         }
     };
-    Ok(ws.on_upgrade(|socket| websocket::serve_play_game(socket, id, state, user)))
+    // Set when reconnecting, to only receive the events missed in the meantime
+    let since = params.get("since").and_then(|s| s.parse().ok());
+    Ok(ws.on_upgrade(move |socket| websocket::serve_play_game(socket, id, since, state, user)))
 }
 
 async fn extract_user(headers: HeaderMap, database: &Database) -> Result<User, &'static str> {
     // Check user info was sent in headers
     let username = match get_auth_token(headers) {
-        Some(token) => {
-            println!("username: {:?}", token);
-            token
-        }
+        Some(token) => token,
         None => {
             return Err("No username found");
         }
@@ -62,23 +68,25 @@ async fn extract_user(headers: HeaderMap, database: &Database) -> Result<User, &
 fn get_auth_token(headers: HeaderMap) -> Option<String> {
     return match headers.get(AUTHORIZATION) {
         Some(token) => {
-            //println!("get_auth_token: {:?}", token);
-            let value = token.to_str().unwrap();
+            // Malformed headers count as no token
+            let value = token.to_str().ok()?;
             let mut parts = value.split(' ');
             let _auth_type = parts.next();
-            Some(parts.next().unwrap().to_string())
+            Some(parts.next()?.to_string())
         }
         None => {
-            println!("get_auth_token: none found");
+            tracing::debug!("get_auth_token: none found");
             None
         }
     };
 }
 
+/// The user's games, a page at a time, see `GameListParams`
 pub async fn get_games(
     headers: HeaderMap,
+    Query(params): Query<GameListParams>,
     State(state): State<SharedState>,
-) -> Result<Json<Vec<GameWithoutMoves>>, StatusCode> {
+) -> Result<Json<GamePage>, StatusCode> {
     tracing::info!("get_games");
 
     let user = match extract_user(headers, &state.db).await {
@@ -88,25 +96,47 @@ pub async fn get_games(
         }
     };
 
-    let games_coll = state.db.collection::<Game>("games");
-    let filter = doc! {
-        "$or": [{"player1": user.name.clone()}, {"player2": user.name}],
-    };
-    let cursor = games_coll.find(filter, None).await;
-    match cursor {
-        Ok(mut cursor) => {
-            let mut games = Vec::new();
-            while let Some(result) = cursor.next().await {
-                match result {
-                    Ok(game) => {
-                        let g = GameWithoutMoves::from_game(game);
-                        games.push(g);
-                    }
-                    Err(err) => error!("{:?}", err),
-                }
-            }
-            Ok(Json(games))
+    let filter = params
+        .filter(&user.name)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let limit = params.limit();
+    match db::find_games(&state.db, filter, limit + 1).await {
+        Ok(games) => Ok(Json(GamePage::new(games, limit))),
+        Err(err) => {
+            error!("{:?}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
+    }
+}
+
+/// Moves played from a position across all finished games, see `explorer::ExplorerMove`
+pub async fn get_explorer(
+    Query(params): Query<explorer::ExplorerParams>,
+    State(state): State<SharedState>,
+) -> Result<Json<explorer::ExplorerResult>, StatusCode> {
+    tracing::info!("get_explorer");
+
+    let key = explorer::position_key(&params.fen).ok_or(StatusCode::BAD_REQUEST)?;
+    match explorer::lookup(&state.db, &key).await {
+        Ok(result) => Ok(Json(result)),
+        Err(err) => {
+            error!("{:?}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Finished public games of all players, see `GameSearchParams`.  No login needed.
+pub async fn search_games(
+    Query(params): Query<GameSearchParams>,
+    State(state): State<SharedState>,
+) -> Result<Json<GamePage>, StatusCode> {
+    tracing::info!("search_games");
+
+    let filter = params.filter().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let limit = params.limit();
+    match db::find_games(&state.db, filter, limit + 1).await {
+        Ok(games) => Ok(Json(GamePage::new(games, limit))),
         Err(err) => {
             error!("{:?}", err);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -115,30 +145,106 @@ pub async fn get_games(
 }
 
 pub async fn get_game(
+    headers: HeaderMap,
     Path(id): Path<String>,
     State(state): State<SharedState>,
 ) -> Result<Json<Game>, StatusCode> {
     tracing::info!("get_game");
+    let username = get_auth_token(headers);
+    match db::get_game(&state.db, &id).await {
+        Some(game) => Ok(Json(game.redacted_for(username.as_deref()))),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateGameParams {
+    /// Only users with the invite token can join
+    #[serde(default)]
+    private: bool,
+    /// Only this user can join
+    opponent: Option<String>,
+    /// `first`, `second` or `random`
+    #[serde(default)]
+    order: PlayOrder,
+    /// Custom starting position, see `Game::from_fen`
+    fen: Option<String>,
+    /// Start from a shuffled position, see `Game::shuffled`
+    #[serde(default)]
+    shuffled: bool,
+    /// Seed for the shuffled position, random if not set.  Implies `shuffled`.
+    seed: Option<u32>,
+    /// Rated games do not allow takebacks
+    #[serde(default)]
+    rated: bool,
+}
+
+pub async fn create_game(
+    headers: HeaderMap,
+    State(state): State<SharedState>,
+    body: String,
+) -> Result<Json<Game>, StatusCode> {
+    tracing::info!("create_game");
+
+    let user = match extract_user(headers, &state.db).await {
+        Ok(user) => user,
+        Err(_) => {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+    };
+
+    // The body is optional, for a public game
+    let params: CreateGameParams = if body.trim().is_empty() {
+        Default::default()
+    } else {
+        serde_json::from_str(&body).map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?
+    };
+
+    let shuffled = params.shuffled || params.seed.is_some();
+    let mut game = match (params.fen, shuffled) {
+        (Some(_), true) => return Err(StatusCode::UNPROCESSABLE_ENTITY),
+        (Some(fen), false) => Game::from_fen(&fen).map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?,
+        (None, true) => Game::shuffled(params.seed.unwrap_or_else(rand::random)),
+        (None, false) => Game::new(),
+    };
+    game.rated = params.rated;
+    if params.private {
+        game.set_invite_token();
+    }
+    if let Some(opponent) = params.opponent {
+        if opponent == user.name || db::get_user(&state.db, &opponent).await.is_none() {
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        game.invited_player = Some(opponent);
+    }
+    game.set_creator(&user, params.order);
     let games_coll = state.db.collection::<Game>("games");
-    let filter = doc! { "pid": id };
-    let result = games_coll.find_one(filter, None).await;
+    let result = games_coll.insert_one(&game, None).await;
     match result {
-        Ok(option) => match option {
-            Some(game) => Ok(Json(game)),
-            None => Err(StatusCode::NOT_FOUND),
-        },
+        Ok(_) => {
+            if let Some(opponent) = &game.invited_player {
+                let notification = Notification::Challenge {
+                    game: game.pid.clone(),
+                    from: user.name.clone(),
+                };
+                state.notifier.notify(opponent, notification).await;
+            }
+            Ok(Json(game))
+        }
         Err(err) => {
             error!("{:?}", err);
-            Err(StatusCode::NOT_FOUND)
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
 }
 
-pub async fn create_game(
+/// Withdraw a game nobody has joined yet
+pub async fn cancel_game(
     headers: HeaderMap,
+    Path(id): Path<String>,
     State(state): State<SharedState>,
 ) -> Result<Json<Game>, StatusCode> {
-    tracing::info!("create_game");
+    tracing::info!("cancel_game");
 
     let user = match extract_user(headers, &state.db).await {
         Ok(user) => user,
@@ -146,20 +252,222 @@ pub async fn create_game(
             return Err(StatusCode::UNAUTHORIZED);
         }
     };
+    let Some(mut game) = db::get_game(&state.db, id.as_str()).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    // Only the creator is seated before anyone joins
+    if game.player1.as_ref() != Some(&user.name) && game.player2.as_ref() != Some(&user.name) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // Fails if someone joined in the meantime
+    if !db::cancel_game(&state.db, &game).await {
+        return Err(StatusCode::CONFLICT);
+    }
+    game.state = GameState::Cancelled;
+    state
+        .hub
+        .publish(&game.pid, "cancel", json!({"state": game.state}));
+
+    Ok(Json(game))
+}
+
+pub async fn export_game(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    tracing::info!("export_game");
+    let Some(game) = db::get_game(&state.db, id.as_str()).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let disposition = format!("attachment; filename=\"{}.txt\"", game.pid);
+    Ok((
+        [
+            (CONTENT_TYPE, "text/plain; charset=utf-8".to_string()),
+            (CONTENT_DISPOSITION, disposition),
+        ],
+        record::export(&game),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BoardImageParams {
+    /// Index into `Game.moves`.  Defaults to the latest position.
+    ply: Option<usize>,
+}
+
+pub async fn get_board_svg(
+    Path(id): Path<String>,
+    Query(params): Query<BoardImageParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    tracing::info!("get_board_svg");
+    let Some(game) = db::get_game(&state.db, id.as_str()).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let game_move = match params.ply {
+        Some(ply) => game.moves.get(ply),
+        None => game.moves.last(),
+    };
+    let Some(game_move) = game_move else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let options = render::SvgOptions {
+        last_move: render::last_move(game_move),
+        coordinates: true,
+    };
+    Ok((
+        [(CONTENT_TYPE, "image/svg+xml")],
+        render::board_svg(
+            &chessops::Position::from_fen(game_move.fen.clone()),
+            &options,
+        ),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FenImageParams {
+    fen: String,
+}
+
+/// Like `get_board_svg`, but for any position, e.g. one from the analysis board
+pub async fn get_fen_board_svg(
+    Query(params): Query<FenImageParams>,
+) -> Result<impl IntoResponse, StatusCode> {
+    tracing::info!("get_fen_board_svg");
+    let pos = chessops::Position::try_from_fen(&params.fen)
+        .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+
+    let options = render::SvgOptions {
+        last_move: None,
+        coordinates: true,
+    };
+    Ok((
+        [(CONTENT_TYPE, "image/svg+xml")],
+        render::board_svg(&pos, &options),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayParams {
+    /// Time each position is shown, in milliseconds, from 20 to 10000
+    delay: Option<u32>,
+}
+
+pub async fn get_replay_gif(
+    Path(id): Path<String>,
+    Query(params): Query<ReplayParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    tracing::info!("get_replay_gif");
+    let Some(game) = db::get_game(&state.db, id.as_str()).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let mut options = render::GifOptions::default();
+    if let Some(delay) = params.delay {
+        options.frame_delay_ms = delay;
+    }
+
+    // Rasterising a long game takes a while, so keep it off the async workers
+    let bytes = tokio::task::spawn_blocking(move || render::replay_gif(&game, &options))
+        .await
+        .map_err(|err| {
+            error!("{:?}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(([(CONTENT_TYPE, "image/gif")], bytes))
+}
+
+pub async fn import_game(
+    headers: HeaderMap,
+    State(state): State<SharedState>,
+    body: String,
+) -> Result<Json<Game>, (StatusCode, Json<record::RecordError>)> {
+    tracing::info!("import_game");
+
+    let Ok(user) = extract_user(headers, &state.db).await else {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(record::RecordError::new("No username found".to_string())),
+        ));
+    };
+
+    let mut game = match record::import(&body) {
+        Ok(game) => game,
+        Err(err) => {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(err)));
+        }
+    };
+    // Users can only import their own games
+    if ![&game.player1, &game.player2]
+        .iter()
+        .any(|p| p.as_deref() == Some(user.name.as_str()))
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(record::RecordError::new(
+                "You must be Player1 or Player2 of the imported game".to_string(),
+            )),
+        ));
+    }
 
-    let mut game = Game::new();
-    game.player1 = Some(user.name);
+    game.waiting_on = game.armies().to_move;
     let games_coll = state.db.collection::<Game>("games");
     let result = games_coll.insert_one(&game, None).await;
     match result {
         Ok(_) => Ok(Json(game)),
         Err(err) => {
             error!("{:?}", err);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(record::RecordError::new("Failed to save game".to_string())),
+            ))
+        }
+    }
+}
+
+type AnalysisRejection = (StatusCode, Json<analysis::AnalysisError>);
+
+/// Run `analyze` off the async workers, since generating moves on a crowded board takes a while
+async fn run_analysis<T, F>(analyze: F) -> Result<Json<T>, AnalysisRejection>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, analysis::AnalysisError> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(analyze).await {
+        Ok(Ok(result)) => Ok(Json(result)),
+        Ok(Err(err)) => Err((StatusCode::UNPROCESSABLE_ENTITY, Json(err))),
+        Err(err) => {
+            error!("{:?}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(analysis::AnalysisError::internal()),
+            ))
         }
     }
 }
 
+/// Play an action on a custom position.  Nothing is stored.
+pub async fn analyze_move(
+    Json(params): Json<analysis::MoveParams>,
+) -> Result<Json<analysis::MoveResult>, AnalysisRejection> {
+    tracing::info!("analyze_move");
+    run_analysis(move || analysis::play(&params)).await
+}
+
+/// List the legal actions on a custom position
+pub async fn analyze_legal(
+    Json(params): Json<analysis::LegalParams>,
+) -> Result<Json<analysis::LegalActions>, AnalysisRejection> {
+    tracing::info!("analyze_legal");
+    run_analysis(move || analysis::legal(&params)).await
+}
+
 pub async fn create_user(State(state): State<SharedState>) -> Result<Json<User>, StatusCode> {
     let user = User::new();
     let user_coll = state.db.collection::<User>("users");
@@ -172,3 +480,74 @@ pub async fn create_user(State(state): State<SharedState>) -> Result<Json<User>,
         }
     }
 }
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookParams {
+    /// `null` removes the webhook
+    url: Option<String>,
+}
+
+/**
+ * Register the URL notifications are posted to, replacing any previous one
+ *
+ * The response holds the webhook's `secret`, which is not shown again.  Each notification is
+ * signed with it, see `notify::SIGNATURE_HEADER`.
+ */
+pub async fn set_webhook(
+    headers: HeaderMap,
+    State(state): State<SharedState>,
+    Json(params): Json<WebhookParams>,
+) -> Result<Json<Option<Webhook>>, (StatusCode, String)> {
+    tracing::info!("set_webhook");
+
+    let user = match extract_user(headers, &state.db).await {
+        Ok(user) => user,
+        Err(err) => {
+            return Err((StatusCode::UNAUTHORIZED, err.to_string()));
+        }
+    };
+    let webhook = match params.url {
+        Some(url) => Some(
+            Webhook::new(&url)
+                .map_err(|err| (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()))?,
+        ),
+        None => None,
+    };
+
+    if let Err(err) = db::save_webhook(&state.db, &user.name, webhook.as_ref()).await {
+        error!("{:?}", err);
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to save webhook".to_string(),
+        ));
+    }
+    Ok(Json(webhook))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn get_auth_token_works() {
+        let headers = |value: HeaderValue| {
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, value);
+            headers
+        };
+        assert_eq!(
+            get_auth_token(headers(HeaderValue::from_static("Bearer anon1"))),
+            Some("anon1".to_string())
+        );
+        assert_eq!(get_auth_token(HeaderMap::new()), None);
+        assert_eq!(
+            get_auth_token(headers(HeaderValue::from_static("Bearer"))),
+            None
+        );
+        assert_eq!(
+            get_auth_token(headers(HeaderValue::from_bytes(b"Bearer \xff").unwrap())),
+            None
+        );
+    }
+}
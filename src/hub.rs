@@ -0,0 +1,216 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+use crate::chat::ChatMessage;
+
+/// Events kept per game so that reconnecting clients can catch up
+const HISTORY_SIZE: usize = 100;
+
+/// Capacity of each game's broadcast channel
+const CHANNEL_SIZE: usize = 100;
+
+/// How long a game's events are kept once nobody is watching it
+const IDLE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// A message broadcast to every connection watching a game, e.g.
+/// `{"seq": 12, "t": "game", "d": {...}}`
+///
+/// Sequence numbers start at 1 and increase by one for each event of the game.
+#[derive(Debug, Serialize)]
+pub struct Event {
+    pub seq: u64,
+    pub t: String,
+    pub d: serde_json::Value,
+}
+
+pub struct Subscription {
+    pub rx: broadcast::Receiver<Arc<Event>>,
+    /// Chat messages, which are neither sequenced nor kept in the history
+    pub chat_rx: broadcast::Receiver<Arc<ChatMessage>>,
+    /// Sequence number of the latest event published before subscribing
+    pub last_seq: u64,
+    /// Events after the requested sequence number, or `None` if they are no longer available and
+    /// the client needs a full snapshot
+    pub missed: Option<Vec<Arc<Event>>>,
+}
+
+#[derive(Debug)]
+struct GameChannel {
+    tx: broadcast::Sender<Arc<Event>>,
+    chat_tx: broadcast::Sender<Arc<ChatMessage>>,
+    history: VecDeque<Arc<Event>>,
+    last_seq: u64,
+    last_active: Instant,
+}
+
+impl GameChannel {
+    fn new() -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_SIZE);
+        let (chat_tx, _) = broadcast::channel(CHANNEL_SIZE);
+        Self {
+            tx,
+            chat_tx,
+            history: VecDeque::with_capacity(HISTORY_SIZE),
+            last_seq: 0,
+            last_active: Instant::now(),
+        }
+    }
+
+    /// Events after `since`, as long as none of them has been dropped from the history
+    fn events_since(&self, since: u64) -> Option<Vec<Arc<Event>>> {
+        if since > self.last_seq {
+            // The client saw a channel we have since evicted
+            return None;
+        }
+        let oldest = self.history.front().map_or(self.last_seq + 1, |e| e.seq);
+        if since + 1 < oldest {
+            return None;
+        }
+
+        Some(
+            self.history
+                .iter()
+                .filter(|e| e.seq > since)
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+/// Per-game broadcast channels, each with a short history of sequenced events, and a separate
+/// one for chat
+#[derive(Debug, Default)]
+pub struct Hub {
+    games: Mutex<HashMap<String, GameChannel>>,
+}
+
+impl Hub {
+    pub fn subscribe(&self, game_id: &str, since: Option<u64>) -> Subscription {
+        let mut games = self.games.lock().unwrap();
+        let channel = games
+            .entry(game_id.to_string())
+            .or_insert_with(GameChannel::new);
+        channel.last_active = Instant::now();
+
+        Subscription {
+            rx: channel.tx.subscribe(),
+            chat_rx: channel.chat_tx.subscribe(),
+            last_seq: channel.last_seq,
+            missed: since.and_then(|since| channel.events_since(since)),
+        }
+    }
+
+    pub fn publish(&self, game_id: &str, t: &str, d: serde_json::Value) {
+        let mut games = self.games.lock().unwrap();
+        let channel = games
+            .entry(game_id.to_string())
+            .or_insert_with(GameChannel::new);
+
+        channel.last_seq += 1;
+        channel.last_active = Instant::now();
+        let event = Arc::new(Event {
+            seq: channel.last_seq,
+            t: t.to_string(),
+            d,
+        });
+
+        if channel.history.len() == HISTORY_SIZE {
+            channel.history.pop_front();
+        }
+        channel.history.push_back(event.clone());
+
+        // Nobody may be listening, which is fine
+        let _ = channel.tx.send(event);
+    }
+
+    /// Send `message` to everyone watching the game, without a sequence number so that chat
+    /// does not push game events out of the history
+    pub fn publish_chat(&self, game_id: &str, message: ChatMessage) {
+        let mut games = self.games.lock().unwrap();
+        let channel = games
+            .entry(game_id.to_string())
+            .or_insert_with(GameChannel::new);
+        channel.last_active = Instant::now();
+        let _ = channel.chat_tx.send(Arc::new(message));
+    }
+
+    pub fn last_seq(&self, game_id: &str) -> u64 {
+        let games = self.games.lock().unwrap();
+        games.get(game_id).map_or(0, |c| c.last_seq)
+    }
+
+    /// Called when a connection closes.  Drops games nobody has watched for a while.
+    pub fn release(&self, game_id: &str) {
+        let mut games = self.games.lock().unwrap();
+        if let Some(channel) = games.get_mut(game_id) {
+            channel.last_active = Instant::now();
+        }
+        games.retain(|_, c| c.tx.receiver_count() > 0 || c.last_active.elapsed() < IDLE_TTL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn subscribe_replays_missed_events() {
+        let hub = Hub::default();
+        for i in 0..3 {
+            hub.publish("game", "game", json!(i));
+        }
+
+        let sub = hub.subscribe("game", Some(1));
+        assert_eq!(sub.last_seq, 3);
+        let missed: Vec<u64> = sub.missed.unwrap().iter().map(|e| e.seq).collect();
+        assert_eq!(missed, vec![2, 3]);
+
+        assert!(hub.subscribe("game", Some(3)).missed.unwrap().is_empty());
+        assert!(hub.subscribe("game", None).missed.is_none());
+        // Unknown to this server, e.g. after a restart
+        assert!(hub.subscribe("game", Some(7)).missed.is_none());
+    }
+
+    #[test]
+    fn subscribe_needs_snapshot_after_history_is_dropped() {
+        let hub = Hub::default();
+        for i in 0..(HISTORY_SIZE + 5) {
+            hub.publish("game", "game", json!(i));
+        }
+
+        assert!(hub.subscribe("game", Some(4)).missed.is_none());
+        assert_eq!(
+            hub.subscribe("game", Some(5)).missed.unwrap().len(),
+            HISTORY_SIZE
+        );
+    }
+
+    #[tokio::test]
+    async fn publish_reaches_subscribers() {
+        let hub = Hub::default();
+        let mut sub = hub.subscribe("game", None);
+        let mut other = hub.subscribe("other", None);
+        hub.publish("game", "game", json!({}));
+
+        assert_eq!(sub.rx.recv().await.unwrap().seq, 1);
+        assert!(other.rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn chat_is_not_sequenced() {
+        let hub = Hub::default();
+        let mut sub = hub.subscribe("game", None);
+        let mut game = crate::game::Game::new();
+        game.pid = "game".to_string();
+        let message = ChatMessage::new(&game, "anon1", "hi").unwrap();
+        hub.publish_chat("game", message);
+
+        assert_eq!(sub.chat_rx.recv().await.unwrap().text, "hi");
+        assert!(sub.rx.try_recv().is_err());
+        assert_eq!(hub.last_seq("game"), 0);
+    }
+}
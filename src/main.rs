@@ -1,8 +1,15 @@
-mod chessops;
+mod analysis;
+mod chat;
 mod db;
+mod explorer;
 mod game;
 mod game_handler;
+mod game_list;
 mod handler;
+mod hub;
+mod notify;
+mod record;
+mod render;
 mod state;
 mod user;
 mod websocket;
@@ -17,7 +24,6 @@ use axum::{
 };
 use mongodb::{options::ClientOptions, Client};
 use std::sync::Arc;
-use tokio::sync::broadcast;
 use tower::ServiceBuilder;
 use tower_http::{
     cors::{Any, CorsLayer},
@@ -25,6 +31,7 @@ use tower_http::{
 };
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+use crate::notify::WebhookSink;
 use crate::state::AppState;
 
 const SOCKET_ADDRESS: &'static str = "0.0.0.0:3000";
@@ -61,15 +68,33 @@ async fn main() {
     let client_options = ClientOptions::parse(db_connection_str).await.unwrap();
     let client = Client::with_options(client_options).unwrap();
     let db = client.database(&db_name);
+    db::create_indexes(&db).await;
+    let explorer_db = db.clone();
+    tokio::spawn(async move { explorer::add_pending_games(&explorer_db).await });
 
-    let (tx, _) = broadcast::channel(100);
-    let app_state = Arc::new(AppState { db, tx });
+    let app_state = Arc::new(AppState {
+        notifier: Arc::new(WebhookSink::new(db.clone())),
+        db,
+        hub: Default::default(),
+        chat_limiter: Default::default(),
+    });
 
     let api_routes = Router::new()
         .route("/games", get(handler::get_games))
         .route("/games", post(handler::create_game))
+        .route("/games/import", post(handler::import_game))
+        .route("/games/search", get(handler::search_games))
         .route("/games/:id", get(handler::get_game))
-        .route("/users", post(handler::create_user));
+        .route("/games/:id/cancel", post(handler::cancel_game))
+        .route("/games/:id/export", get(handler::export_game))
+        .route("/games/:id/board.svg", get(handler::get_board_svg))
+        .route("/games/:id/replay.gif", get(handler::get_replay_gif))
+        .route("/board.svg", get(handler::get_fen_board_svg))
+        .route("/users", post(handler::create_user))
+        .route("/users/webhook", post(handler::set_webhook))
+        .route("/analysis/move", post(handler::analyze_move))
+        .route("/analysis/legal", post(handler::analyze_legal))
+        .route("/explorer", get(handler::get_explorer));
 
     let app = Router::new()
         .route("/", get(|| async { "Hello, World!" }))
@@ -0,0 +1,470 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use mongodb::Database;
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt::Debug;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use crate::db;
+use crate::game::{Game, GameResult};
+
+/// Header carrying `sha256=<hex HMAC of the body>`, keyed with the webhook's secret
+pub const SIGNATURE_HEADER: &str = "X-Sochess-Signature";
+
+/// Waits between delivery attempts.  The first attempt is made right away.
+const RETRY_DELAYS: [Duration; 3] = [
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+];
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where a user's notifications are posted
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Webhook {
+    pub url: String,
+    /// Shared with the user once, when registering the webhook, to verify signatures
+    pub secret: String,
+}
+
+impl Webhook {
+    /// Fails unless `url` is an absolute HTTP(S) URL
+    pub fn new(url: &str) -> Result<Self, &'static str> {
+        let parsed = reqwest::Url::parse(url).map_err(|_| "Invalid webhook URL")?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err("Webhook URLs must use HTTP or HTTPS");
+        }
+        Ok(Self {
+            url: parsed.to_string(),
+            secret: nanoid!(32),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Notification {
+    /// The user has to move in `game`
+    YourTurn { game: String },
+    /// `from` created `game` for the user to join
+    Challenge { game: String, from: String },
+    GameEnded {
+        game: String,
+        result: Option<GameResult>,
+    },
+}
+
+/// The body posted to webhooks, e.g.
+/// `{"event": "your_turn", "game": "...", "user": "anon1", "ts": "2024-06-10T12:00:00Z"}`
+#[derive(Debug, Serialize)]
+struct Payload<'a> {
+    #[serde(flatten)]
+    notification: &'a Notification,
+    /// Who the notification is for
+    user: &'a str,
+    ts: DateTime<Utc>,
+}
+
+/// Delivers notifications to users who are not necessarily connected
+#[async_trait]
+pub trait NotificationSink: Debug + Send + Sync {
+    /// Must not wait for the delivery, which may take a while
+    async fn notify(&self, username: &str, notification: Notification);
+}
+
+/// Posts notifications to the webhook each user registered, if any
+#[derive(Debug)]
+pub struct WebhookSink {
+    db: Database,
+}
+
+impl WebhookSink {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl NotificationSink for WebhookSink {
+    async fn notify(&self, username: &str, notification: Notification) {
+        let Some(webhook) = db::get_user(&self.db, username)
+            .await
+            .and_then(|user| user.webhook)
+        else {
+            return;
+        };
+        let payload = Payload {
+            notification: &notification,
+            user: username,
+            ts: Utc::now(),
+        };
+        let body = serde_json::to_vec(&payload).unwrap();
+        tokio::spawn(deliver(webhook, body, &RETRY_DELAYS, false));
+    }
+}
+
+/// The notifications due after `before` became `after`, as a result of an action by `actor`
+pub fn notifications(before: &Game, after: &Game, actor: &str) -> Vec<(String, Notification)> {
+    let mut due = Vec::new();
+    let players = [after.player1.clone(), after.player2.clone()];
+
+    if after.result.is_some() && before.result.is_none() {
+        for player in players.into_iter().flatten() {
+            let notification = Notification::GameEnded {
+                game: after.pid.clone(),
+                result: after.result.clone(),
+            };
+            due.push((player, notification));
+        }
+        return due;
+    }
+
+    // Moves, joins and takebacks can all hand over the turn
+    let to_move = after.armies().to_move;
+    if let Some(player) = to_move {
+        if player != actor && before.armies().to_move.as_ref() != Some(&player) {
+            let notification = Notification::YourTurn {
+                game: after.pid.clone(),
+            };
+            due.push((player, notification));
+        }
+    }
+    due
+}
+
+/// `sha256=` followed by the hex HMAC-SHA256 of `body`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    let hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256={}", hex)
+}
+
+/// Whether `ip` is on the server's own machine or network, which webhooks must not reach
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // Shared address space for carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64)
+                // Benchmarking, 198.18.0.0/15
+                || (a == 198 && b & 0xfe == 18)
+                // Multicast and reserved, 224.0.0.0/4 and 240.0.0.0/4
+                || a >= 224
+        }
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_internal(IpAddr::V4(mapped));
+            }
+            let segments = ip.segments();
+            // NAT64, 64:ff9b::/96, embeds an IPv4 address in the last 32 bits
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., a, b, c, d] = ip.octets();
+                return is_internal(IpAddr::V4(Ipv4Addr::new(a, b, c, d)));
+            }
+            let first = segments[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local, fc00::/7
+                || first & 0xfe00 == 0xfc00
+                // Link-local, fe80::/10
+                || first & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Resolved {
+    /// The host and its addresses
+    Addrs(String, Vec<SocketAddr>),
+    /// The host could not be resolved right now
+    Unreachable,
+    /// The URL is invalid or points at an internal address
+    Forbidden,
+}
+
+/// The host of `url` and the addresses it resolves to, unless any of them is internal
+async fn resolve(url: &str, allow_internal: bool) -> Resolved {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return Resolved::Forbidden;
+    };
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Resolved::Forbidden;
+    };
+    // IPv6 hosts come in brackets
+    let host = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let addrs: Vec<SocketAddr> = match tokio::net::lookup_host((host.as_str(), port)).await {
+        Ok(addrs) => addrs.collect(),
+        Err(_) => return Resolved::Unreachable,
+    };
+    if addrs.is_empty() {
+        return Resolved::Unreachable;
+    }
+    if !allow_internal && addrs.iter().any(|addr| is_internal(addr.ip())) {
+        return Resolved::Forbidden;
+    }
+    Resolved::Addrs(host, addrs)
+}
+
+/// What became of one attempt to post to a webhook
+#[derive(Debug, PartialEq)]
+enum Attempt {
+    Accepted,
+    /// The receiver is unreachable or failed in a way that may go away
+    Failed,
+    /// Trying again will not help
+    Rejected,
+}
+
+/// Post `body` to the webhook once.  The request only goes to the addresses `resolve` checked,
+/// so that the webhook's DNS records cannot be changed in between, and redirects are not
+/// followed.
+async fn attempt(webhook: &Webhook, signature: &str, body: &[u8], allow_internal: bool) -> Attempt {
+    let (host, addrs) = match resolve(&webhook.url, allow_internal).await {
+        Resolved::Addrs(host, addrs) => (host, addrs),
+        Resolved::Unreachable => return Attempt::Failed,
+        Resolved::Forbidden => {
+            tracing::warn!("Refusing to post to webhook {}", webhook.url);
+            return Attempt::Rejected;
+        }
+    };
+    let http = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve_to_addrs(&host, &addrs)
+        .build()
+        .unwrap();
+    let response = http
+        .post(&webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, signature)
+        .body(body.to_vec())
+        .send()
+        .await;
+    match response {
+        Ok(response) if response.status().is_success() => Attempt::Accepted,
+        Ok(response) => {
+            let status = response.status();
+            // Other client errors and redirects will not go away by trying again
+            if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                Attempt::Failed
+            } else {
+                Attempt::Rejected
+            }
+        }
+        Err(_) => Attempt::Failed,
+    }
+}
+
+/// Post `body` to the webhook, retrying after each of `delays` if the receiver is unreachable
+/// or fails.  Returns whether the receiver accepted it.  Webhooks on the server's own machine
+/// or network are refused unless `allow_internal` is set, which is only meant for tests.
+async fn deliver(
+    webhook: Webhook,
+    body: Vec<u8>,
+    delays: &'static [Duration],
+    allow_internal: bool,
+) -> bool {
+    let signature = sign(&webhook.secret, &body);
+    let mut delays = delays.iter();
+    loop {
+        let retry = match attempt(&webhook, &signature, &body, allow_internal).await {
+            Attempt::Accepted => return true,
+            Attempt::Failed => true,
+            Attempt::Rejected => false,
+        };
+
+        match delays.next() {
+            Some(delay) if retry => tokio::time::sleep(*delay).await,
+            _ => {
+                tracing::warn!("Giving up on webhook {}", webhook.url);
+                return false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameState;
+    use crate::user::User;
+    use axum::{body::Bytes, extract::State, http::HeaderMap, http::StatusCode, routing::post};
+    use std::sync::{Arc, Mutex};
+
+    /// Signature header and body of each request
+    type Received = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+    /// Stands in for a user's webhook receiver: fails the first `failures` requests, then
+    /// records the signature and body of each one
+    #[derive(Clone, Default)]
+    struct StubReceiver {
+        failures: Arc<Mutex<usize>>,
+        received: Received,
+    }
+
+    impl StubReceiver {
+        async fn start(failures: usize) -> (Self, String) {
+            let stub = Self {
+                failures: Arc::new(Mutex::new(failures)),
+                ..Default::default()
+            };
+            let app = axum::Router::new()
+                .route("/hook", post(Self::receive))
+                .with_state(stub.clone());
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/hook", listener.local_addr().unwrap());
+            tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+            (stub, url)
+        }
+
+        async fn receive(State(stub): State<Self>, headers: HeaderMap, body: Bytes) -> StatusCode {
+            let mut failures = stub.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return StatusCode::SERVICE_UNAVAILABLE;
+            }
+            let signature = headers[SIGNATURE_HEADER].to_str().unwrap().to_string();
+            stub.received
+                .lock()
+                .unwrap()
+                .push((signature, body.to_vec()));
+            StatusCode::NO_CONTENT
+        }
+    }
+
+    const NO_WAIT: [Duration; 2] = [Duration::ZERO, Duration::ZERO];
+
+    #[tokio::test]
+    async fn deliver_retries_and_signs() {
+        let (stub, url) = StubReceiver::start(2).await;
+        let webhook = Webhook::new(&url).unwrap();
+        let body = br#"{"event":"your_turn","game":"abc"}"#.to_vec();
+
+        assert!(deliver(webhook.clone(), body.clone(), &NO_WAIT, true).await);
+        let received = stub.received.lock().unwrap().clone();
+        assert_eq!(received, vec![(sign(&webhook.secret, &body), body)]);
+    }
+
+    #[tokio::test]
+    async fn deliver_gives_up() {
+        let (stub, url) = StubReceiver::start(3).await;
+        let webhook = Webhook::new(&url).unwrap();
+
+        assert!(!deliver(webhook, b"{}".to_vec(), &NO_WAIT, true).await);
+        assert!(stub.received.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn deliver_refuses_internal_addresses() {
+        let (stub, url) = StubReceiver::start(0).await;
+        let webhook = Webhook::new(&url).unwrap();
+
+        assert!(!deliver(webhook.clone(), b"{}".to_vec(), &NO_WAIT, false).await);
+        assert!(stub.received.lock().unwrap().is_empty());
+
+        let localhost = url.replace("127.0.0.1", "localhost");
+        assert_eq!(resolve(&localhost, false).await, Resolved::Forbidden);
+        assert!(matches!(
+            resolve(&localhost, true).await,
+            Resolved::Addrs(host, _) if host == "localhost"
+        ));
+    }
+
+    #[test]
+    fn is_internal_works() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "100.64.0.1",
+            "100.127.255.254",
+            "198.18.0.1",
+            "198.19.255.255",
+            "224.0.0.1",
+            "239.255.255.250",
+            "240.0.0.1",
+            "64:ff9b::10.0.0.1",
+            "64:ff9b::7f00:1",
+        ] {
+            assert!(is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "93.184.216.34",
+            "100.128.0.1",
+            "198.20.0.1",
+            "223.255.255.255",
+            "2606:2800:220:1::",
+            "64:ff9b::5db8:d822",
+        ] {
+            assert!(!is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn webhook_new_rejects_invalid_urls() {
+        assert!(Webhook::new("https://example.com/hook").is_ok());
+        assert!(Webhook::new("example.com/hook").is_err());
+        assert!(Webhook::new("ftp://example.com/hook").is_err());
+    }
+
+    #[test]
+    fn notifications_works() {
+        let mut before = Game::new();
+        before.player1 = Some("anon1".to_string());
+        let mut after = before.clone();
+        after.set_player_joined(&User {
+            name: "anon2".to_string(),
+            webhook: None,
+        });
+
+        // Joining hands the first move to the creator
+        let due = notifications(&before, &after, "anon2");
+        assert_eq!(
+            due,
+            vec![(
+                "anon1".to_string(),
+                Notification::YourTurn {
+                    game: after.pid.clone()
+                }
+            )]
+        );
+        // Nothing changed for anon1
+        assert!(notifications(&after, &after, "anon2").is_empty());
+
+        let mut ended = after.clone();
+        ended.set_ended(GameResult::Player1Won);
+        let due = notifications(&after, &ended, "anon1");
+        assert_eq!(due.len(), 2);
+        assert!(matches!(ended.state, GameState::Ended));
+        assert!(due
+            .iter()
+            .all(|(_, n)| matches!(n, Notification::GameEnded { .. })));
+    }
+}
@@ -0,0 +1,383 @@
+use chessops::{PositionError, San, SanAction};
+use chrono::{NaiveDate, TimeZone, Utc};
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+
+use crate::game::{Game, GameResult, GameState};
+
+const MAX_LINE_LENGTH: usize = 80;
+
+const UNKNOWN: &str = "?";
+
+#[derive(Debug, Serialize)]
+pub struct RecordError {
+    message: String,
+}
+
+impl Error for RecordError {}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl RecordError {
+    pub fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
+/**
+ * Textual game record modelled on PGN
+ *
+ * Tag pairs come first, one per line, e.g. `[Player1 "anon1234567"]`:
+ * - GameId
+ * - Date: `YYYY.MM.DD`
+ * - Player1, Player2: `?` if unknown
+ * - Result: `1-0` if Player 1 won, `0-1` if Player 2 won, `1/2-1/2` for a draw, `*` otherwise
+ * - TimeControl: `-` for none
+ * - FEN: starting position using our custom FEN notation
+ *
+ * The move section follows a blank line.  Every action is written in short algebraic notation
+ * (see chessops::San), prefixed by the move number whenever the ply changes.  Besides moves, this
+ * includes defections (`D:navy`) and Player 2's choice after the first move (`accept` or
+ * `reject`), which is kept with the first move.  The section ends with the result.
+ */
+pub fn export(game: &Game) -> String {
+    let mut output = String::new();
+
+    let tags = [
+        ("GameId", game.pid.clone()),
+        ("Date", game.created.format("%Y.%m.%d").to_string()),
+        (
+            "Player1",
+            game.player1.clone().unwrap_or(UNKNOWN.to_string()),
+        ),
+        (
+            "Player2",
+            game.player2.clone().unwrap_or(UNKNOWN.to_string()),
+        ),
+        ("Result", result_to_str(&game.result).to_string()),
+        ("TimeControl", "-".to_string()),
+        ("FEN", game.start_fen().to_string()),
+    ];
+    for (name, value) in tags {
+        output.push_str(&format!("[{} \"{}\"]\n", name, escape(&value)));
+    }
+    output.push('\n');
+
+    let mut tokens = Vec::new();
+    let mut last_ply = None;
+    for (prev, entry) in game.moves.iter().zip(game.moves.iter().skip(1)) {
+        let pos = chessops::Position::from_fen(prev.fen.clone());
+        // Player 2's choice belongs with the first move
+        let is_choice = entry.san == "action:accept" || entry.san == "action:reject";
+        if !is_choice && last_ply != Some(pos.ply()) {
+            tokens.push(format!("{}.", pos.ply() + 1));
+            last_ply = Some(pos.ply());
+        }
+
+        let notation = if entry.notation.is_empty() {
+            notation_from_san(&pos, &entry.san)
+        } else {
+            entry.notation.clone()
+        };
+        tokens.push(notation);
+    }
+    tokens.push(result_to_str(&game.result).to_string());
+
+    let mut line_length = 0;
+    for token in tokens {
+        if line_length > 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+            output.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            output.push(' ');
+            line_length += 1;
+        }
+        line_length += token.len();
+        output.push_str(&token);
+    }
+    output.push('\n');
+
+    output
+}
+
+/// Replay a record through `Position`, rejecting it if any action is illegal
+pub fn import(record: &str) -> Result<Game, RecordError> {
+    let mut game = Game::new();
+    let mut result_token = None;
+    let mut movetext = Vec::new();
+
+    for line in record.lines().map(|l| l.trim()) {
+        if line.starts_with('[') {
+            let (name, value) = parse_tag(line)?;
+            match name.as_str() {
+                "Date" => {
+                    if let Ok(date) = NaiveDate::parse_from_str(&value, "%Y.%m.%d") {
+                        let created = Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap());
+                        game.created = created;
+                    }
+                }
+                "Player1" if value != UNKNOWN => game.player1 = Some(value),
+                "Player2" if value != UNKNOWN => game.player2 = Some(value),
+                "Result" => result_token = Some(value),
+                "FEN" => game.moves[0].fen = value,
+                _ => {}
+            }
+        } else {
+            movetext.extend(line.split_whitespace().map(|t| t.to_string()));
+        }
+    }
+
+    let mut pos = chessops::Position::try_from_fen(game.start_fen())
+        .map_err(|err| RecordError::new(format!("Invalid starting position: {}", err)))?;
+    game.state = if pos.p1_owned().is_some() {
+        GameState::InProgress
+    } else if game.player2.is_some() {
+        GameState::Accepted
+    } else {
+        GameState::Created
+    };
+
+    for token in movetext {
+        if is_move_number(&token) {
+            continue;
+        }
+        if let Some(result) = result_from_str(&token) {
+            if result_token
+                .as_ref()
+                .is_some_and(|t| result_from_str(t) != Some(result.clone()))
+            {
+                return Err(RecordError::new(
+                    "Result tag does not match the move section".to_string(),
+                ));
+            }
+            result_token = Some(token);
+            break;
+        }
+        if matches!(game.state, GameState::Ended) {
+            return Err(RecordError::new(format!(
+                "Action after game end: {}",
+                token
+            )));
+        }
+
+        replay_action(&mut game, &mut pos, &token)?;
+    }
+
+    // Games may also end by resignation or on time, which the moves do not show, so the declared
+    // result is kept as long as it does not contradict a checkmate found in the replay
+    match result_token.and_then(|t| result_from_str(&t)).flatten() {
+        Some(result) if game.result.is_none() => game.set_ended(result),
+        Some(result) if game.result.as_ref() != Some(&result) => {
+            return Err(RecordError::new(format!(
+                "Result {} does not match the final position",
+                result_to_str(&Some(result))
+            )));
+        }
+        _ => {}
+    }
+
+    game.imported = true;
+    Ok(game)
+}
+
+/// Apply a single action to `pos` and record it on `game`, following the same state
+/// transitions as `GameHandler`
+fn replay_action(
+    game: &mut Game,
+    pos: &mut chessops::Position,
+    token: &str,
+) -> Result<(), RecordError> {
+    let illegal = || RecordError::new(format!("Illegal action: {}", token));
+
+    match (&game.state, token) {
+        (GameState::FirstMove, "accept") => {
+            pos.accept_first_move();
+            game.state = GameState::InProgress;
+            game.add_move(pos.to_fen(), "action:accept".to_string(), token.to_string());
+            return Ok(());
+        }
+        (GameState::FirstMove, "reject") => {
+            pos.reject_first_move();
+            game.state = GameState::InProgress;
+            game.add_move(pos.to_fen(), "action:reject".to_string(), token.to_string());
+            return Ok(());
+        }
+        (GameState::Created | GameState::Accepted | GameState::InProgress, _) => {}
+        (GameState::DefectMoveKing, _) => {}
+        _ => return Err(illegal()),
+    }
+
+    let action = San::parse(pos, token)
+        .map_err(|err| RecordError::new(format!("Cannot read action {}: {:?}", token, err)))?;
+    match action {
+        SanAction::Defect(color) => {
+            if !matches!(game.state, GameState::InProgress) {
+                return Err(illegal());
+            }
+            let name = color.to_name();
+            match pos.defect_to(color) {
+                Ok(()) => {
+                    game.add_move(
+                        pos.to_fen(),
+                        format!("action:defect:{}", name),
+                        San::from_defect(&color),
+                    );
+                }
+                Err(PositionError::DefectMoveKing) => {
+                    game.state = GameState::DefectMoveKing;
+                    game.add_move(
+                        pos.to_fen(),
+                        format!("action:defect:{}*", name),
+                        San::from_defect(&color),
+                    );
+                }
+                Err(_) => return Err(illegal()),
+            }
+        }
+        SanAction::Move(chess_move) => {
+            let pos_before = pos.clone();
+            let result = match game.state {
+                GameState::DefectMoveKing => pos.play_move_after_defect(&chess_move),
+                _ => pos.play_move(&chess_move),
+            };
+            if result.is_err() {
+                return Err(illegal());
+            }
+
+            let notation = San::from_move(&pos_before, &chess_move);
+            game.add_move(pos.to_fen(), chess_move.to_san(), notation);
+
+            if pos.is_checkmate() {
+                game.set_ended(GameResult::win_for(pos_before.active_player()));
+            } else if pos_before.p1_owned().is_none() {
+                game.state = GameState::FirstMove;
+            } else {
+                game.state = GameState::InProgress;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Games stored before we kept short notation only have the long form
+fn notation_from_san(pos: &chessops::Position, san: &str) -> String {
+    if let Some(choice) = san.strip_prefix("action:") {
+        if let Some(color) = choice.strip_prefix("defect:") {
+            let color = chessops::Color::from_str(color.trim_end_matches('*'));
+            return color.map(|c| San::from_defect(&c)).unwrap_or_default();
+        }
+        return choice.to_string();
+    }
+
+    San::from_move(pos, &chessops::Move::from_san(san))
+}
+
+fn parse_tag(line: &str) -> Result<(String, String), RecordError> {
+    let invalid = || RecordError::new(format!("Invalid tag pair: {}", line));
+
+    let inner = line
+        .strip_prefix('[')
+        .and_then(|l| l.strip_suffix(']'))
+        .ok_or_else(invalid)?;
+    let (name, value) = inner.split_once(' ').ok_or_else(invalid)?;
+    let value = value
+        .trim()
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .ok_or_else(invalid)?;
+
+    Ok((name.to_string(), unescape(value)))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn unescape(value: &str) -> String {
+    value.replace("\\\"", "\"").replace("\\\\", "\\")
+}
+
+fn is_move_number(token: &str) -> bool {
+    token
+        .strip_suffix('.')
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn result_to_str(result: &Option<GameResult>) -> &'static str {
+    match result {
+        Some(GameResult::Player1Won) => "1-0",
+        Some(GameResult::Player2Won) => "0-1",
+        Some(GameResult::Draw) => "1/2-1/2",
+        None => "*",
+    }
+}
+
+/// `None` if `token` is not a result, `Some(None)` if the game is unfinished
+fn result_from_str(token: &str) -> Option<Option<GameResult>> {
+    match token {
+        "1-0" => Some(Some(GameResult::Player1Won)),
+        "0-1" => Some(Some(GameResult::Player2Won)),
+        "1/2-1/2" => Some(Some(GameResult::Draw)),
+        "*" => Some(None),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_and_import_works() {
+        let mut game = Game::new();
+        game.player1 = Some("anon1".to_string());
+        game.player2 = Some("anon2".to_string());
+        game.state = GameState::Accepted;
+        for token in ["wNg3", "accept", "bNg14", "wNh5"] {
+            let mut pos = chessops::Position::from_fen(game.moves.last().unwrap().fen.clone());
+            replay_action(&mut game, &mut pos, token).unwrap();
+        }
+
+        let record = export(&game);
+        assert!(record.contains("[Player1 \"anon1\"]\n"));
+        assert!(record.contains("[Result \"*\"]\n"));
+        assert!(record.ends_with("\n1. wNg3 accept 2. bNg14 3. wNh5 *\n"));
+
+        let imported = import(&record).unwrap();
+        assert_eq!(imported.player1, game.player1);
+        assert_eq!(imported.player2, game.player2);
+        assert!(matches!(imported.state, GameState::InProgress));
+        assert_eq!(
+            imported.moves.iter().map(|m| &m.fen).collect::<Vec<_>>(),
+            game.moves.iter().map(|m| &m.fen).collect::<Vec<_>>(),
+        );
+        assert_eq!(imported.moves[2].san, "action:accept".to_string());
+    }
+
+    #[test]
+    fn import_rejects_illegal_actions() {
+        assert!(import("1. wNg4 *").is_err());
+        // Player 2 must accept or reject the first move
+        assert!(import("1. wNg3 2. wNh5 *").is_err());
+        assert!(import("[Result \"1-0\"]\n\n1. wNg3 0-1").is_err());
+        assert!(import("[FEN \"16/16 1 - - - - 0\"]\n\n*").is_err());
+    }
+
+    #[test]
+    fn import_keeps_declared_results() {
+        // e.g. Player 2 resigned
+        let game = import("1. wNg3 accept 1-0").unwrap();
+        assert_eq!(game.result, Some(GameResult::Player1Won));
+        assert!(matches!(game.state, GameState::Ended));
+        assert!(game.imported);
+
+        let game = import("[Result \"1/2-1/2\"]\n\n1. wNg3 accept").unwrap();
+        assert_eq!(game.result, Some(GameResult::Draw));
+    }
+}
@@ -0,0 +1,62 @@
+mod raster;
+mod svg;
+
+pub use raster::{replay_gif, GifOptions};
+pub use svg::{board_svg, SvgOptions};
+
+use chessops::{Color, LookupTables, Square, BOARD_SIZE};
+use std::collections::HashMap;
+
+use crate::game;
+
+pub type Rgb = (u8, u8, u8);
+
+pub const LIGHT_SQUARE: Rgb = (240, 217, 181);
+pub const DARK_SQUARE: Rgb = (181, 136, 99);
+pub const LAST_MOVE: Rgb = (246, 246, 105);
+
+pub fn army_rgb(color: &Color) -> Rgb {
+    match color {
+        Color::Ash => (200, 200, 200),
+        Color::Black => (34, 34, 34),
+        Color::Cyan => (0, 188, 212),
+        Color::Green => (46, 158, 68),
+        Color::Navy => (31, 58, 147),
+        Color::Orange => (242, 140, 40),
+        Color::Pink => (240, 98, 146),
+        Color::Red => (211, 47, 47),
+        Color::Slate => (90, 100, 112),
+        Color::Violet => (142, 68, 173),
+        Color::White => (250, 250, 250),
+        Color::Yellow => (244, 208, 63),
+    }
+}
+
+/// Whether black or white reads better on top of `rgb`
+pub fn is_dark(rgb: Rgb) -> bool {
+    let (r, g, b) = rgb;
+    (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000 < 128
+}
+
+/// The army color of each of the 24 colored squares
+pub fn colored_squares() -> HashMap<Square, Color> {
+    let mut squares = HashMap::new();
+    for (color, bitboard) in LookupTables::build_clear_colored_squares() {
+        for i in 0..BOARD_SIZE {
+            if bitboard.get(i) == Some(false) {
+                squares.insert(Square::from_index(i), color);
+            }
+        }
+    }
+    squares
+}
+
+/// Origin and destination squares of a recorded move, or `None` for other game actions
+pub fn last_move(game_move: &game::Move) -> Option<(Square, Square)> {
+    if game_move.san.is_empty() || game_move.san.starts_with("action:") {
+        return None;
+    }
+
+    let chess_move = chessops::Move::from_san(&game_move.san);
+    Some((chess_move.from, chess_move.to))
+}
@@ -0,0 +1,369 @@
+use chessops::{Color, Position, Role, Square, BOARD_WIDTH};
+use gif::{Encoder, Frame, Repeat};
+use std::collections::HashMap;
+
+use crate::game::Game;
+use crate::render::{self, Rgb};
+
+const SQUARE_SIZE: usize = 32;
+const BOARD_PIXELS: usize = SQUARE_SIZE * BOARD_WIDTH;
+
+/// Piece silhouettes are drawn on a 16x16 grid and scaled up to the square size
+const SPRITE_SIZE: usize = 16;
+const SPRITE_SCALE: usize = SQUARE_SIZE / SPRITE_SIZE;
+
+// Palette layout.  Army colors follow `Color::all()` order, first plain then highlighted.
+const LIGHT: u8 = 0;
+const DARK: u8 = 1;
+const LIGHT_HIGHLIGHT: u8 = 2;
+const DARK_HIGHLIGHT: u8 = 3;
+const BLACK_OUTLINE: u8 = 4;
+const WHITE_OUTLINE: u8 = 5;
+const ARMY: u8 = 6;
+const ARMY_HIGHLIGHT: u8 = ARMY + 12;
+const PALETTE_SIZE: usize = 32;
+
+/// Longer games only show their last `MAX_FRAMES` positions, to bound the work per request
+const MAX_FRAMES: usize = 300;
+
+/// Bounds on `GifOptions::frame_delay_ms`
+const MIN_FRAME_DELAY_MS: u32 = 20;
+const MAX_FRAME_DELAY_MS: u32 = 10_000;
+
+#[derive(Debug)]
+pub struct GifOptions {
+    /// Time each position is shown, in milliseconds.  Clamped to 20 ms to 10 s.
+    pub frame_delay_ms: u32,
+}
+
+impl GifOptions {
+    /// The delay in hundredths of a second, as GIFs store it
+    fn frame_delay(&self) -> u16 {
+        (self
+            .frame_delay_ms
+            .clamp(MIN_FRAME_DELAY_MS, MAX_FRAME_DELAY_MS)
+            / 10) as u16
+    }
+}
+
+/// Index in `game.moves` of the first position shown, see `MAX_FRAMES`
+fn first_frame(game: &Game) -> usize {
+    game.moves.len().saturating_sub(MAX_FRAMES)
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        Self {
+            frame_delay_ms: 800,
+        }
+    }
+}
+
+/// Animate the positions of `game`, at most `MAX_FRAMES` of them, holding the final position a
+/// little longer
+pub fn replay_gif(game: &Game, options: &GifOptions) -> Vec<u8> {
+    let delay = options.frame_delay();
+    let colored_squares = render::colored_squares();
+    let first = first_frame(game);
+
+    let mut output = Vec::new();
+    {
+        let size = BOARD_PIXELS as u16;
+        let mut encoder =
+            Encoder::new(&mut output, size, size, &palette()).expect("Failed to write GIF header");
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .expect("Failed to write GIF header");
+
+        for (i, game_move) in game.moves.iter().enumerate().skip(first) {
+            let pos = Position::from_fen(game_move.fen.clone());
+            let pixels = rasterize(&pos, render::last_move(game_move), &colored_squares);
+
+            let mut frame = Frame::from_indexed_pixels(size, size, pixels, None);
+            frame.delay = if i == game.moves.len() - 1 {
+                delay * 3
+            } else {
+                delay
+            };
+            encoder
+                .write_frame(&frame)
+                .expect("Failed to write GIF frame");
+        }
+    }
+
+    output
+}
+
+/// Draw the board as palette indices, rank 16 at the top.  `colored_squares` is
+/// `render::colored_squares()`, built once per GIF.
+fn rasterize(
+    pos: &Position,
+    last_move: Option<(Square, Square)>,
+    colored_squares: &HashMap<Square, Color>,
+) -> Vec<u8> {
+    let mut pixels = vec![DARK; BOARD_PIXELS * BOARD_PIXELS];
+
+    for index in 0..(BOARD_WIDTH * BOARD_WIDTH) {
+        let square = Square::from_index(index);
+        let is_highlighted = last_move.is_some_and(|(from, to)| square == from || square == to);
+        let is_light = (square.file().to_index() + square.rank_index()) % 2 == 1;
+
+        let fill = match (colored_squares.get(&square), is_highlighted) {
+            (Some(color), false) => ARMY + palette_offset(color),
+            (Some(color), true) => ARMY_HIGHLIGHT + palette_offset(color),
+            (None, false) if is_light => LIGHT,
+            (None, false) => DARK,
+            (None, true) if is_light => LIGHT_HIGHLIGHT,
+            (None, true) => DARK_HIGHLIGHT,
+        };
+
+        let (x0, y0) = square_origin(&square);
+        for y in y0..(y0 + SQUARE_SIZE) {
+            pixels[y * BOARD_PIXELS + x0..y * BOARD_PIXELS + x0 + SQUARE_SIZE].fill(fill);
+        }
+    }
+
+    for (square, piece) in &pos.board().by_square {
+        let sprite = sprite(&piece.role);
+        let fill = ARMY + palette_offset(&piece.color);
+        let outline = if render::is_dark(render::army_rgb(&piece.color)) {
+            WHITE_OUTLINE
+        } else {
+            BLACK_OUTLINE
+        };
+        let is_set = |x: isize, y: isize| {
+            x >= 0
+                && y >= 0
+                && (x as usize) < SPRITE_SIZE
+                && (y as usize) < SPRITE_SIZE
+                && sprite[y as usize].as_bytes()[x as usize] == b'#'
+        };
+
+        let (x0, y0) = square_origin(square);
+        for sy in 0..SPRITE_SIZE {
+            for sx in 0..SPRITE_SIZE {
+                let (x, y) = (sx as isize, sy as isize);
+                if !is_set(x, y) {
+                    continue;
+                }
+                let is_edge =
+                    !(is_set(x - 1, y) && is_set(x + 1, y) && is_set(x, y - 1) && is_set(x, y + 1));
+                let index = if is_edge { outline } else { fill };
+
+                for py in 0..SPRITE_SCALE {
+                    let row = (y0 + sy * SPRITE_SCALE + py) * BOARD_PIXELS;
+                    let col = x0 + sx * SPRITE_SCALE;
+                    pixels[row + col..row + col + SPRITE_SCALE].fill(index);
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+fn square_origin(square: &Square) -> (usize, usize) {
+    let x = square.file().to_index() * SQUARE_SIZE;
+    let y = (BOARD_WIDTH - 1 - square.rank_index()) * SQUARE_SIZE;
+    (x, y)
+}
+
+fn palette_offset(color: &Color) -> u8 {
+    Color::all().iter().position(|c| c == color).unwrap() as u8
+}
+
+/// Flattened RGB palette, in the order of the index constants above
+fn palette() -> Vec<u8> {
+    fn highlight(rgb: Rgb) -> Rgb {
+        let (r, g, b) = rgb;
+        let (hr, hg, hb) = render::LAST_MOVE;
+        (
+            ((r as u16 + hr as u16) / 2) as u8,
+            ((g as u16 + hg as u16) / 2) as u8,
+            ((b as u16 + hb as u16) / 2) as u8,
+        )
+    }
+
+    let mut colors = vec![
+        render::LIGHT_SQUARE,
+        render::DARK_SQUARE,
+        highlight(render::LIGHT_SQUARE),
+        highlight(render::DARK_SQUARE),
+        (0, 0, 0),
+        (255, 255, 255),
+    ];
+    colors.extend(Color::all().iter().map(render::army_rgb));
+    colors.extend(Color::all().iter().map(|c| highlight(render::army_rgb(c))));
+    colors.resize(PALETTE_SIZE, (0, 0, 0));
+
+    colors.into_iter().flat_map(|(r, g, b)| [r, g, b]).collect()
+}
+
+fn sprite(role: &Role) -> [&'static str; SPRITE_SIZE] {
+    match role {
+        Role::Pawn => [
+            "................",
+            "................",
+            "................",
+            "......####......",
+            ".....######.....",
+            ".....######.....",
+            "......####......",
+            ".....######.....",
+            "......####......",
+            "......####......",
+            ".....######.....",
+            "....########....",
+            "...##########...",
+            "...##########...",
+            "................",
+            "................",
+        ],
+        Role::Knight => [
+            "................",
+            "................",
+            "......##........",
+            ".....####.......",
+            "....#######.....",
+            "...#########....",
+            "...###.######...",
+            "..####..#####...",
+            ".......######...",
+            "......######....",
+            ".....######.....",
+            "....########....",
+            "...##########...",
+            "...##########...",
+            "................",
+            "................",
+        ],
+        Role::Bishop => [
+            "................",
+            ".......##.......",
+            "......####......",
+            ".....###.##.....",
+            ".....##.###.....",
+            ".....######.....",
+            "......####......",
+            "......####......",
+            ".....######.....",
+            "......####......",
+            "......####......",
+            "....########....",
+            "...##########...",
+            "...##########...",
+            "................",
+            "................",
+        ],
+        Role::Rook => [
+            "................",
+            "................",
+            "...##.####.##...",
+            "...##.####.##...",
+            "...##########...",
+            "....########....",
+            ".....######.....",
+            ".....######.....",
+            ".....######.....",
+            ".....######.....",
+            ".....######.....",
+            "....########....",
+            "...##########...",
+            "...##########...",
+            "................",
+            "................",
+        ],
+        Role::Queen => [
+            "................",
+            "..#....##....#..",
+            "..##..####..##..",
+            "..###.####.###..",
+            "...##########...",
+            "...##########...",
+            "....########....",
+            ".....######.....",
+            ".....######.....",
+            ".....######.....",
+            "....########....",
+            "....########....",
+            "...##########...",
+            "...##########...",
+            "................",
+            "................",
+        ],
+        Role::King => [
+            "................",
+            ".......##.......",
+            "......####......",
+            ".......##.......",
+            "....##.##.##....",
+            "...##########...",
+            "...##########...",
+            "....########....",
+            ".....######.....",
+            ".....######.....",
+            ".....######.....",
+            "....########....",
+            "...##########...",
+            "...##########...",
+            "................",
+            "................",
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprites_are_square() {
+        for role in [
+            Role::Pawn,
+            Role::Knight,
+            Role::Bishop,
+            Role::Rook,
+            Role::Queen,
+            Role::King,
+        ] {
+            assert!(sprite(&role).iter().all(|row| row.len() == SPRITE_SIZE));
+        }
+    }
+
+    #[test]
+    fn replay_gif_works() {
+        let mut game = Game::new();
+        game.add_move(
+            "16/16/16/16/16/16/bk15/16/16/16/16/16/16/02wr13/16/wk15 1 w - b - 0".to_string(),
+            "WRc01c03".to_string(),
+            "wRc3".to_string(),
+        );
+        let bytes = replay_gif(&game, &GifOptions::default());
+
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(bytes.as_slice())
+            .unwrap();
+        assert_eq!(decoder.width() as usize, BOARD_PIXELS);
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, vec![80, 240]);
+    }
+
+    #[test]
+    fn replay_gif_is_bounded() {
+        let mut game = Game::new();
+        assert_eq!(first_frame(&game), 0);
+        let fen = game.moves[0].fen.clone();
+        for _ in 0..MAX_FRAMES {
+            game.add_move(fen.clone(), "action:reject".to_string(), String::new());
+        }
+        assert_eq!(first_frame(&game), 1);
+
+        let delay = |frame_delay_ms| GifOptions { frame_delay_ms }.frame_delay();
+        assert_eq!(delay(0), 2);
+        assert_eq!(delay(800), 80);
+        assert_eq!(delay(u32::MAX), 1000);
+    }
+}
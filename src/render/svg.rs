@@ -0,0 +1,173 @@
+use chessops::{Position, Role, Square, BOARD_WIDTH};
+use std::fmt::Write;
+
+use crate::render::{self, Rgb};
+
+const SQUARE_SIZE: usize = 40;
+const COORDINATES_MARGIN: usize = 20;
+
+#[derive(Debug, Default)]
+pub struct SvgOptions {
+    /// Origin and destination squares to highlight
+    pub last_move: Option<(Square, Square)>,
+    /// Draw file letters and rank numbers around the board
+    pub coordinates: bool,
+}
+
+/// Render the 16x16 board, rank 16 at the top
+pub fn board_svg(pos: &Position, options: &SvgOptions) -> String {
+    let margin = if options.coordinates {
+        COORDINATES_MARGIN
+    } else {
+        0
+    };
+    let board_size = SQUARE_SIZE * BOARD_WIDTH;
+    let width = board_size + margin;
+    let height = board_size + margin;
+    let colored_squares = render::colored_squares();
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, width, height,
+    );
+    let _ = write!(
+        svg,
+        r#"<rect width="{}" height="{}" fill="{}"/>"#,
+        width,
+        height,
+        hex(render::DARK_SQUARE),
+    );
+
+    for index in 0..(BOARD_WIDTH * BOARD_WIDTH) {
+        let square = Square::from_index(index);
+        let (x, y) = square_origin(&square, margin);
+
+        let fill = match colored_squares.get(&square) {
+            Some(color) => render::army_rgb(color),
+            None if (square.file().to_index() + square.rank_index()) % 2 == 1 => {
+                render::LIGHT_SQUARE
+            }
+            None => render::DARK_SQUARE,
+        };
+        let _ = write!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+            x,
+            y,
+            SQUARE_SIZE,
+            SQUARE_SIZE,
+            hex(fill),
+        );
+    }
+
+    if let Some((from, to)) = &options.last_move {
+        for square in [from, to] {
+            let (x, y) = square_origin(square, margin);
+            let _ = write!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" fill-opacity="0.5"/>"#,
+                x,
+                y,
+                SQUARE_SIZE,
+                SQUARE_SIZE,
+                hex(render::LAST_MOVE),
+            );
+        }
+    }
+
+    let mut pieces: Vec<_> = pos.board().by_square.iter().collect();
+    pieces.sort_by_key(|(square, _)| square.to_index());
+    for (square, piece) in pieces {
+        let (x, y) = square_origin(square, margin);
+        let fill = render::army_rgb(&piece.color);
+        let stroke = if render::is_dark(fill) {
+            "#ffffff"
+        } else {
+            "#000000"
+        };
+        let _ = write!(
+            svg,
+            r#"<text x="{}" y="{}" font-size="{}" text-anchor="middle" dominant-baseline="central" fill="{}" stroke="{}" stroke-width="1">{}</text>"#,
+            x + SQUARE_SIZE / 2,
+            y + SQUARE_SIZE / 2,
+            SQUARE_SIZE * 4 / 5,
+            hex(fill),
+            stroke,
+            glyph(&piece.role),
+        );
+    }
+
+    if options.coordinates {
+        for i in 0..BOARD_WIDTH {
+            let square = Square::from_file_and_rank_index(i, i);
+            let _ = write!(
+                svg,
+                r##"<text x="{}" y="{}" font-size="12" text-anchor="middle" dominant-baseline="central" fill="#ffffff">{}</text>"##,
+                margin + i * SQUARE_SIZE + SQUARE_SIZE / 2,
+                board_size + margin / 2,
+                square.file().to_str(),
+            );
+            let _ = write!(
+                svg,
+                r##"<text x="{}" y="{}" font-size="12" text-anchor="middle" dominant-baseline="central" fill="#ffffff">{}</text>"##,
+                margin / 2,
+                (BOARD_WIDTH - 1 - i) * SQUARE_SIZE + SQUARE_SIZE / 2,
+                i + 1,
+            );
+        }
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Top-left corner of `square`, leaving room on the left for rank numbers
+fn square_origin(square: &Square, margin: usize) -> (usize, usize) {
+    let x = margin + square.file().to_index() * SQUARE_SIZE;
+    let y = (BOARD_WIDTH - 1 - square.rank_index()) * SQUARE_SIZE;
+    (x, y)
+}
+
+fn glyph(role: &Role) -> char {
+    match role {
+        Role::King => '\u{265A}',
+        Role::Queen => '\u{265B}',
+        Role::Rook => '\u{265C}',
+        Role::Bishop => '\u{265D}',
+        Role::Knight => '\u{265E}',
+        Role::Pawn => '\u{265F}',
+    }
+}
+
+fn hex(rgb: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb.0, rgb.1, rgb.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn board_svg_works() {
+        let fen = "16/16/16/16/16/16/bk15/16/16/16/16/16/16/02wr13/16/wk15 1 w - b - 0";
+        let options = SvgOptions {
+            last_move: Some((Square::C1, Square::C3)),
+            coordinates: true,
+        };
+        let svg = board_svg(&Position::from_fen(fen.to_string()), &options);
+
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>"));
+        // Background, 256 squares and 2 highlights
+        assert_eq!(svg.matches("<rect").count(), 1 + 256 + 2);
+        // 3 pieces and 32 coordinates
+        assert_eq!(svg.matches("<text").count(), 3 + 32);
+        // Navy colored square
+        assert!(svg.contains(&format!(
+            r#"<rect x="180" y="440" width="40" height="40" fill="{}"/>"#,
+            hex(render::army_rgb(&chessops::Color::Navy)),
+        )));
+    }
+}
@@ -1,11 +1,16 @@
 use mongodb::Database;
 use std::sync::Arc;
-use tokio::sync::broadcast;
+
+use crate::chat::ChatLimiter;
+use crate::hub::Hub;
+use crate::notify::NotificationSink;
 
 pub type SharedState = Arc<AppState>;
 
 #[derive(Debug)]
 pub struct AppState {
-    pub tx: broadcast::Sender<String>,
+    pub hub: Hub,
     pub db: Database,
+    pub notifier: Arc<dyn NotificationSink>,
+    pub chat_limiter: ChatLimiter,
 }
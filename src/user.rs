@@ -1,9 +1,15 @@
 use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
 
+use crate::notify::Webhook;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct User {
     pub name: String,
+
+    /// Where to post the user's notifications, see `notify::WebhookSink`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<Webhook>,
 }
 
 impl User {
@@ -11,6 +17,7 @@ impl User {
         Self {
             // TODO: Enforce uniqueness
             name: format!("anon{}", nanoid!(7)),
+            webhook: None,
         }
     }
 }
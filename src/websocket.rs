@@ -1,113 +1,271 @@
 use axum::extract::ws::{Message, WebSocket};
 use futures::{sink::SinkExt, stream::StreamExt};
+use serde_json::json;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{self, Instant};
 
+use crate::chat::{ChatChannel, ChatLimiter, ChatMessage};
 use crate::db;
+use crate::game::GameWithArmies;
 use crate::game_handler::GameHandler;
 use crate::state::SharedState;
 use crate::user::User;
 
-pub async fn serve_play_game(socket: WebSocket, id: String, state: SharedState, user: User) {
+/// How often we ping the client
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Connections we have not heard from in this long are considered dead
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(45);
+
+/**
+ * Every broadcast event carries a per-game sequence number: `{"seq": 3, "t": "move", "d": {...}}`
+ *
+ * On connect, the client gets a `game` event with the full game and the latest sequence number.
+ * The game also holds `active_player`, `to_move` (a username) and `player1_armies`/
+ * `player2_armies` (`{"owned": "white", "controlled": ["navy"]}`), see `Armies`.
+ * A client reconnecting with `?since=N` instead only gets the events after N, unless they are
+ * too old, in which case it gets the full game as above.
+ *
+ * After that, only what changed is sent:
+ * - `move` for each entry added to `Game.moves`, including first move choices, defections
+ *   and premoves.  `d` holds `ply`, the new entry's index in `moves`, the entry itself as `move`,
+ *   and the game's `state` and `result`.  The new position is `move.fen`.
+ * - `join` when the opponent joins.  `d` holds `player1`, `player2` and `state`.  Private games need
+ *   `{"t": "join", "d": {"token": "..."}}` with the token from the invite link.
+ * - `cancel` when the creator cancels the game before anyone joined.  `d` holds `state`.
+ * - `takeback_request` when a player asks for a takeback, or their opponent declines it.  `d`
+ *   holds `by`, the requesting player or `null`.
+ * - `takeback` when a takeback is accepted.  `d` holds `ply`, the index of the last entry left in
+ *   `moves`, and the game's `state`.
+ *
+ * Chat messages sent with `{"t": "chat", "d": {"text": "..."}}` come as `{"t": "chat", "d": ...}`
+ * where `d` is a `ChatMessage`.  They are not sequenced, so a reconnecting client does not get
+ * the ones it missed as events.  Instead, every client gets `{"t": "chat_history", "d": [...]}`
+ * with the latest messages right after connecting, oldest first.  Players and spectators each
+ * have their own channel, and only get the messages of theirs.
+ *
+ * Replies meant only for this connection are not sequenced:
+ * - `{"t": "error", "d": {"message": "..."}}` when an action fails, or a chat message is refused
+ *   for being empty, too long or sent too fast
+ * - `{"t": "pong"}` in answer to `{"t": "ping"}`, for clients that cannot send ping frames
+ * - `{"t": "premove", "d": {"player": 1, "san": "..."}}` once a premove is queued, with `d` set
+ *   to `null` once it is cancelled.  A premove played after the opponent's move shows up as a
+ *   regular `move` event; one that has become illegal is dropped without notice.
+ */
+pub async fn serve_play_game(
+    socket: WebSocket,
+    id: String,
+    since: Option<u64>,
+    state: SharedState,
+    user: User,
+) {
     let span = tracing::info_span!("handle_socket");
     let _enter = span.enter();
     tracing::info!("connection opened");
 
     let (mut sender, mut receiver) = socket.split();
 
-    let game_option = db::get_game(&state.db, id.as_str()).await;
+    // Subscribe before loading the game so that no event falls in between
+    let mut subscription = state.hub.subscribe(&id, since);
 
-    let Some(game) = game_option else {
+    let Some(game) = db::get_game(&state.db, id.as_str()).await else {
         // TODO: Send an error message and close connection
+        state.hub.release(&id);
         return;
     };
+    // Spectators who join the game move over to the players' channel
+    let mut channel = ChatChannel::for_user(&game, &user.name);
 
-    // Send a response as soon as connection is opened
-    if sender
-        .send(Message::Text(serde_json::to_string(&game).unwrap()))
-        .await
-        .is_err()
-    {
-        // client disconnected
-        return;
+    let mut initial: Vec<String> = match subscription.missed.take() {
+        Some(events) => events
+            .iter()
+            .map(|event| serde_json::to_string(&**event).unwrap())
+            .collect(),
+        None => {
+            let game = GameWithArmies::from_game(game.redacted_for(Some(&user.name)));
+            let snapshot = json!({"seq": subscription.last_seq, "t": "game", "d": game});
+            vec![snapshot.to_string()]
+        }
+    };
+    let messages = db::get_chat_messages(&state.db, &id, channel).await;
+    initial.push(json!({"t": "chat_history", "d": messages}).to_string());
+    for msg in initial {
+        if sender.send(Message::Text(msg)).await.is_err() {
+            // client disconnected
+            state.hub.release(&id);
+            return;
+        }
     }
 
-    let cloned_state = state.clone();
-    let cloned_user = user.clone();
-    let tx = state.tx.clone();
-    const ERR_PREFIX: &'static str = "error:";
-
-    // Wait for messages and broadcast them to all subscribers
-    let mut recv_task = tokio::spawn(async move {
-        while let Some(Ok(Message::Text(msg))) = receiver.next().await {
-            tracing::info!("received msg={}", msg);
-
-            // We need the latest game state
-            let game_option = db::get_game(&cloned_state.db, id.as_str()).await;
-            let Some(game) = game_option else {
-                return;
-            };
-
-            // Determine message type
-            // Process message
-            // Broadcast update to all clients
-            let mut handler =
-                GameHandler::new(game.clone(), cloned_user.clone(), cloned_state.db.clone());
-            let json = handler
-                .read(&msg)
-                .expect("Failed to parse websocket message");
-            //let result = handler.process(json).await;
-            match handler.process(json).await {
-                Ok(_) => {
-                    // Let's try sending the latest game object back each time.
-                    // We can optimize later.
-                    let game_option = db::get_game(&cloned_state.db, id.as_str()).await;
-                    if let Some(game) = game_option {
-                        let response = serde_json::to_string(&game).unwrap();
-                        let _ = tx.send(response);
-                    } else {
-                        tracing::error!("Error fetching game after processing message");
+    let chat_limiter = ChatLimiter::per_connection();
+    let mut heartbeat = time::interval(HEARTBEAT_INTERVAL);
+    let mut last_heard = Instant::now();
+
+    loop {
+        let outgoing = tokio::select! {
+            incoming = receiver.next() => {
+                let Some(Ok(msg)) = incoming else {
+                    break;
+                };
+                last_heard = Instant::now();
+
+                match msg {
+                    Message::Text(msg) => {
+                        handle_message(&state, &id, &user, &chat_limiter, &msg).await
                     }
-                }
-                Err(err) => {
-                    // HACK: Add a prefix so we can send errors only to the user who made the move.
-                    let msg = ERR_PREFIX.to_string()
-                        + &cloned_user.name
-                        + &serde_json::to_string(&err).unwrap();
-                    let _ = tx.send(msg);
+                    Message::Close(_) => break,
+                    // Pongs only matter for `last_heard`, and axum answers pings itself
+                    _ => None,
                 }
             }
-        }
-    });
-
-    let mut rx = state.tx.subscribe();
-
-    // Receive broadcast messages from above and forward them to all clients
-    let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            // If the message contains a username, then we only send that message to the
-            // appropriate user
-            if msg.starts_with(ERR_PREFIX) {
-                let for_this_user = ERR_PREFIX.to_string() + &user.name;
-
-                if msg.starts_with(&for_this_user) {
-                    let parts: Vec<&str> = msg.as_str().split(&for_this_user).collect();
-                    let data = parts[1]; // First part will be an empty string
-                    if sender.send(Message::Text(data.to_string())).await.is_err() {
-                        break;
+            event = subscription.rx.recv() => match event {
+                Ok(event) => {
+                    let joined = ["player1", "player2"]
+                        .iter()
+                        .any(|p| event.d[p] == user.name.as_str());
+                    if event.t == "join" && joined {
+                        channel = ChatChannel::Players;
                     }
+                    Some(serde_json::to_string(&*event).unwrap())
                 }
-            } else {
-                if sender.send(Message::Text(msg)).await.is_err() {
+                Err(RecvError::Lagged(_)) => {
+                    // Too far behind to replay, start over from the latest game
+                    let Some(game) = db::get_game(&state.db, id.as_str()).await else {
+                        break;
+                    };
+                    channel = ChatChannel::for_user(&game, &user.name);
+                    let game = GameWithArmies::from_game(game.redacted_for(Some(&user.name)));
+                    let seq = state.hub.last_seq(&id);
+                    Some(json!({"seq": seq, "t": "game", "d": game}).to_string())
+                }
+                Err(RecvError::Closed) => break,
+            },
+            message = subscription.chat_rx.recv() => match message {
+                Ok(message) => (message.channel == channel)
+                    .then(|| json!({"t": "chat", "d": *message}).to_string()),
+                // Chat is not worth a snapshot
+                Err(RecvError::Lagged(_)) => None,
+                Err(RecvError::Closed) => break,
+            },
+            _ = heartbeat.tick() => {
+                if last_heard.elapsed() > HEARTBEAT_TIMEOUT {
+                    tracing::info!("connection timed out");
+                    break;
+                }
+                if sender.send(Message::Ping(Vec::new())).await.is_err() {
                     break;
                 }
+                None
+            }
+        };
+
+        if let Some(msg) = outgoing {
+            if sender.send(Message::Text(msg)).await.is_err() {
+                break;
             }
         }
-    });
+    }
 
-    // If any one of the tasks run to completion, we abort the other.
-    tokio::select! {
-        _ = (&mut send_task) => recv_task.abort(),
-        _ = (&mut recv_task) => send_task.abort(),
+    drop(subscription);
+    state.hub.release(&id);
+    tracing::info!("connection closed");
+}
+
+/// Process a client message.  Successful actions are broadcast to everyone watching the game;
+/// the returned reply, if any, is only for this connection.
+async fn handle_message(
+    state: &SharedState,
+    id: &str,
+    user: &User,
+    chat_limiter: &ChatLimiter,
+    msg: &str,
+) -> Option<String> {
+    tracing::info!("received msg={}", msg);
+
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(msg) else {
+        return Some(json!({"t": "error", "d": {"message": "Invalid message"}}).to_string());
     };
+    if json["t"] == "ping" {
+        return Some(json!({"t": "pong"}).to_string());
+    }
+    if json["t"] == "chat" {
+        return post_chat(state, id, user, chat_limiter, &json["d"]["text"]).await;
+    }
 
-    tracing::info!("connection closed");
+    // We need the latest game state
+    let game = db::get_game(&state.db, id).await?;
+    let ply_before = game.moves.len();
+    let takeback_before = game.takeback_by;
+    let players_before = (game.player1.clone(), game.player2.clone());
+    let mut handler =
+        GameHandler::new(game, user.clone(), state.db.clone(), state.notifier.clone());
+
+    match handler.process(json).await {
+        Ok(_) => {
+            // The handler's copy of the game is up to date, no need to read it back
+            let game = handler.game();
+            let ply = game.moves.len() - 1;
+            if game.moves.len() > ply_before {
+                // A premove may have been played right after the move
+                for ply in ply_before..game.moves.len() {
+                    let data = json!({
+                        "ply": ply,
+                        "move": game.moves[ply],
+                        "state": game.state,
+                        "result": game.result,
+                    });
+                    state.hub.publish(id, "move", data);
+                }
+            } else if game.moves.len() < ply_before {
+                let data = json!({"ply": ply, "state": game.state});
+                state.hub.publish(id, "takeback", data);
+            } else if game.takeback_by != takeback_before {
+                let data = json!({"by": game.takeback_by});
+                state.hub.publish(id, "takeback_request", data);
+            } else if (game.player1.clone(), game.player2.clone()) != players_before {
+                let data = json!({
+                    "player1": game.player1,
+                    "player2": game.player2,
+                    "state": game.state,
+                });
+                state.hub.publish(id, "join", data);
+            } else {
+                // Only the premove changed, which is nobody else's business
+                return Some(json!({"t": "premove", "d": game.premove}).to_string());
+            }
+            None
+        }
+        Err(err) => Some(json!({"t": "error", "d": err}).to_string()),
+    }
+}
+
+/// Save and broadcast a chat message, if both the connection's `chat_limiter` and the user's
+/// allow it.  Returns an error reply if it was refused.
+async fn post_chat(
+    state: &SharedState,
+    id: &str,
+    user: &User,
+    chat_limiter: &ChatLimiter,
+    text: &serde_json::Value,
+) -> Option<String> {
+    let error = |message: &str| Some(json!({"t": "error", "d": {"message": message}}).to_string());
+
+    let Some(text) = text.as_str() else {
+        return error("Invalid message");
+    };
+    let game = db::get_game(&state.db, id).await?;
+    let message = match ChatMessage::new(&game, &user.name, text) {
+        Ok(message) => message,
+        Err(err) => return error(err),
+    };
+    if !ChatLimiter::allow_all(&[chat_limiter, &state.chat_limiter], &user.name) {
+        return error("You are sending messages too fast");
+    }
+    if !db::save_chat_message(&state.db, &message).await {
+        return error("Failed to send message");
+    }
+
+    state.hub.publish_chat(id, message);
+    None
 }
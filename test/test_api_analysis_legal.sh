@@ -0,0 +1 @@
+curl http://localhost:3000/api/analysis/legal -X POST -H "Content-Type: application/json" -d '{"fen": "bk15/16/16/16/16/16/16/01nr14/16/16/16/16/16/16/16/wk15 1 w n b - 3"}'
@@ -0,0 +1 @@
+curl http://localhost:3000/api/games/$1/cancel -X POST -H "Authorization: Test anon2JKujdY"
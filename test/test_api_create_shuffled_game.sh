@@ -0,0 +1 @@
+curl http://localhost:3000/api/games -X POST -H "Authorization: Test anon2JKujdY" -H "Content-Type: application/json" -d '{"shuffled": true, "seed": 42}'
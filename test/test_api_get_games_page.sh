@@ -0,0 +1 @@
+curl "http://localhost:3000/api/games?state=InProgress&my_turn=true&limit=10" -H "Authorization: Test anon2JKujdY"
@@ -0,0 +1 @@
+curl "http://localhost:3000/api/games/search?player=anon2JKujdY&result=Player1Won&min_moves=2&from=2024-01-01T00:00:00Z"
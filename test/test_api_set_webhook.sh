@@ -0,0 +1 @@
+curl http://localhost:3000/api/users/webhook -X POST -H "Authorization: Test anon2JKujdY" -H "Content-Type: application/json" -d '{"url": "http://localhost:4000/hook"}'